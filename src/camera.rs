@@ -1,11 +1,99 @@
 use std::f32::consts::PI;
+use std::time::Duration;
 
+use bevy::input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel};
 use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_egui::egui::{self, Align2, RichText};
+use bevy_egui::EguiContexts;
 use space_editor::prelude::*;
 
-use crate::level::Gameplay;
+use crate::events::{Exploded, UnitReachedGoal};
+use crate::level::{Gameplay, Level};
+use crate::settings::Settings;
+use crate::spells::{cast_spell, Spells};
+use crate::spline::Curve;
+use crate::unit::Unit;
 use crate::utils::smooth_damp_vec3;
 
+#[derive(Resource, Clone, Copy, Default, PartialEq)]
+pub enum CameraMode {
+    #[default]
+    Free,
+    /// Keep the camera target on a unit
+    Follow(Entity),
+    /// Fly along a [`CameraPath`] before handing over to the camera target
+    Intro { path: Entity, start: Duration },
+}
+
+/// Trauma based screen shake, decays over time
+#[derive(Resource, Clone, Default)]
+pub struct Trauma {
+    trauma: f32,
+    offset: Vec3,
+}
+
+impl Trauma {
+    const DECAY: f32 = 1.5;
+    const MAX_OFFSET: f32 = 1.5;
+    /// Explosions further away than this from the camera target are not felt
+    const RANGE: f32 = 30.0;
+
+    pub fn add(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    fn shake(&mut self, time: f32, delta: f32) -> Vec3 {
+        self.trauma = (self.trauma - Self::DECAY * delta).max(0.0);
+        let strength = self.trauma * self.trauma * Self::MAX_OFFSET;
+        self.offset = Vec3::new(
+            (time * 31.0).sin() + (time * 17.3).sin() * 0.5,
+            (time * 27.0).sin() + (time * 11.9).cos() * 0.5,
+            (time * 23.0).cos() + (time * 19.1).sin() * 0.5,
+        ) * (strength / 1.5);
+        self.offset
+    }
+}
+
+fn add_trauma(
+    mut trauma: ResMut<Trauma>,
+    mut exploded: EventReader<Exploded>,
+    mut reached: EventReader<UnitReachedGoal>,
+    targets: Query<&GlobalTransform, With<CameraTarget>>,
+    settings: Res<Settings>,
+) {
+    if !settings.screen_shake {
+        exploded.clear();
+        reached.clear();
+        return;
+    }
+    let Some(target) = targets.iter().next().map(|gt| gt.translation()) else {
+        return;
+    };
+    for ev in exploded.read() {
+        let falloff = 1.0 - ev.position.distance(target) / Trauma::RANGE;
+        if falloff > 0.0 {
+            trauma.add(0.5 * falloff);
+        }
+    }
+    for _ in reached.read() {
+        trauma.add(0.4);
+    }
+}
+
+/// Marks a spline as the intro flyover path instead of a lane for units
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component, Default)]
+pub struct CameraPath {
+    duration: f32,
+}
+
+impl Default for CameraPath {
+    fn default() -> Self {
+        Self { duration: 6.0 }
+    }
+}
+
 #[derive(Component, Reflect, Clone)]
 #[reflect(Component, Default)]
 pub struct CameraTarget {
@@ -15,8 +103,14 @@ pub struct CameraTarget {
     distance: f32,
     orbit: f32,
     orbit_angle: f32,
+    /// Half extents of the area the player can pan around the starting position
+    bounds: Vec2,
+    min_distance: f32,
+    max_distance: f32,
     #[reflect(ignore)]
     velocity: f32,
+    #[reflect(ignore)]
+    origin: Option<Vec3>,
 }
 
 impl Default for CameraTarget {
@@ -29,15 +123,272 @@ impl Default for CameraTarget {
             velocity: 0.0,
             orbit: 0.0,
             orbit_angle: 0.0,
+            bounds: Vec2::splat(20.0),
+            min_distance: 10.0,
+            max_distance: 60.0,
+            origin: None,
+        }
+    }
+}
+
+impl CameraTarget {
+    const MIN_ANGLE: f32 = 20.0;
+    const MAX_ANGLE: f32 = 85.0;
+
+    /// Direction along the ground that points "up" on the screen
+    fn forward(&self) -> Vec3 {
+        -Vec3::new(self.orbit_angle.cos(), 0.0, self.orbit_angle.sin())
+    }
+
+    fn pan(&mut self, trans: &mut Transform, delta: Vec2) {
+        let forward = self.forward();
+        let right = forward.cross(Vec3::Y);
+        let pos = trans.translation + (right * delta.x + forward * delta.y) * self.distance;
+        self.move_to(trans, Vec2::new(pos.x, pos.z));
+    }
+
+    /// Move the target to a position on the ground, within the bounds
+    pub fn move_to(&mut self, trans: &mut Transform, pos: Vec2) {
+        let origin = *self.origin.get_or_insert(trans.translation);
+        trans.translation.x = pos
+            .x
+            .clamp(origin.x - self.bounds.x, origin.x + self.bounds.x);
+        trans.translation.z = pos
+            .y
+            .clamp(origin.z - self.bounds.y, origin.z + self.bounds.y);
+    }
+
+    fn zoom(&mut self, factor: f32) {
+        self.distance = (self.distance * factor).clamp(self.min_distance, self.max_distance);
+    }
+
+    fn rotate(&mut self, orbit: f32, tilt: f32) {
+        self.orbit_angle += orbit;
+        self.angle = (self.angle + tilt).clamp(Self::MIN_ANGLE, Self::MAX_ANGLE);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn control_camera(
+    mut targets: Query<(&mut CameraTarget, &mut Transform)>,
+    units: Query<&GlobalTransform, With<Unit>>,
+    mut mode: ResMut<CameraMode>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut motion: EventReader<MouseMotion>,
+    mut wheel: EventReader<MouseWheel>,
+    touches: Res<Touches>,
+    mut contexts: EguiContexts,
+    time: Res<Time>,
+) {
+    let ctx = contexts.ctx_mut();
+    let use_pointer = !ctx.wants_pointer_input() && !ctx.is_pointer_over_area();
+    let use_keys = !ctx.wants_keyboard_input();
+    let delta = time.delta_seconds();
+
+    let axis = |neg: &[KeyCode], pos: &[KeyCode]| {
+        (keys.any_pressed(pos.iter().copied()) as i8 - keys.any_pressed(neg.iter().copied()) as i8)
+            as f32
+    };
+    let mut pan = Vec2::ZERO;
+    let mut zoom = 1.0;
+    let mut orbit = 0.0;
+    let mut tilt = 0.0;
+    if use_keys {
+        pan.x = axis(
+            &[KeyCode::KeyA, KeyCode::ArrowLeft],
+            &[KeyCode::KeyD, KeyCode::ArrowRight],
+        );
+        pan.y = axis(
+            &[KeyCode::KeyS, KeyCode::ArrowDown],
+            &[KeyCode::KeyW, KeyCode::ArrowUp],
+        );
+        pan *= 0.5 * delta;
+        orbit += axis(&[KeyCode::KeyE], &[KeyCode::KeyQ]) * delta;
+        tilt += axis(&[KeyCode::KeyF], &[KeyCode::KeyR]) * 45.0 * delta;
+        zoom -= axis(
+            &[KeyCode::Minus, KeyCode::NumpadSubtract],
+            &[KeyCode::Equal, KeyCode::NumpadAdd],
+        ) * delta;
+    }
+
+    let drag: Vec2 = motion.read().map(|m| m.delta).sum();
+    let scroll: f32 = wheel
+        .read()
+        .map(|w| match w.unit {
+            MouseScrollUnit::Line => w.y,
+            MouseScrollUnit::Pixel => w.y / 20.0,
+        })
+        .sum();
+    if use_pointer {
+        zoom -= scroll * 0.1;
+        if mouse.pressed(MouseButton::Right) {
+            orbit += drag.x * 0.005;
+            tilt += drag.y * 0.2;
+        }
+        if mouse.pressed(MouseButton::Middle) {
+            pan += Vec2::new(-drag.x, drag.y) * 0.002;
+        }
+        let touching: Vec<_> = touches.iter().collect();
+        match touching.as_slice() {
+            [one] => pan += Vec2::new(-one.delta().x, one.delta().y) * 0.002,
+            [a, b, ..] => {
+                let prev = a.previous_position().distance(b.previous_position());
+                let next = a.position().distance(b.position());
+                if next > 1.0 {
+                    zoom *= prev / next;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let CameraMode::Follow(unit) = *mode {
+        match units.get(unit) {
+            Ok(gt) if pan == Vec2::ZERO => {
+                for (_, mut trans) in targets.iter_mut() {
+                    let pos = gt.translation();
+                    trans.translation.x = pos.x;
+                    trans.translation.z = pos.z;
+                }
+            }
+            _ => *mode = CameraMode::Free,
         }
     }
+
+    for (mut target, mut trans) in targets.iter_mut() {
+        target.pan(&mut trans, pan);
+        target.zoom(zoom);
+        target.rotate(orbit, tilt);
+    }
+}
+
+/// Screen distance (in logical pixels) for clicking on units
+const PICK_RADIUS: f32 = 30.0;
+
+/// Click on a unit (or press tab to cycle through them) to follow it with the camera
+#[allow(clippy::too_many_arguments)]
+fn select_unit(
+    mut mode: ResMut<CameraMode>,
+    units: Query<(Entity, &GlobalTransform), With<Unit>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut contexts: EguiContexts,
+    spells: Res<Spells>,
+) {
+    if keys.just_pressed(KeyCode::Tab) {
+        let current = match *mode {
+            CameraMode::Follow(e) => Some(e),
+            _ => None,
+        };
+        let mut entities = units.iter().map(|(e, _)| e);
+        let next = match current {
+            Some(current) => entities
+                .by_ref()
+                .skip_while(|&e| e != current)
+                .nth(1)
+                .or_else(|| units.iter().next().map(|(e, _)| e)),
+            None => entities.next(),
+        };
+        if let Some(next) = next {
+            *mode = CameraMode::Follow(next);
+        }
+    }
+    if mouse.just_pressed(MouseButton::Left)
+        && spells.aiming.is_none()
+        && !contexts.ctx_mut().is_pointer_over_area()
+    {
+        let Some(cursor) = windows.get_single().ok().and_then(|w| w.cursor_position()) else {
+            return;
+        };
+        let Some((camera, camera_gt)) = cameras.iter().find(|(c, _)| c.is_active) else {
+            return;
+        };
+        let mut closest = (PICK_RADIUS * PICK_RADIUS, None);
+        for (entity, gt) in units.iter() {
+            if let Some(pos) = camera.world_to_viewport(camera_gt, gt.translation()) {
+                let dist = pos.distance_squared(cursor);
+                if dist < closest.0 {
+                    closest = (dist, Some(entity));
+                }
+            }
+        }
+        if let (_, Some(unit)) = closest {
+            *mode = CameraMode::Follow(unit);
+        }
+    }
+}
+
+fn start_intro(
+    paths: Query<Entity, (With<CameraPath>, Added<Curve>)>,
+    mut mode: ResMut<CameraMode>,
+    time: Res<Time>,
+) {
+    for path in paths.iter() {
+        *mode = CameraMode::Intro {
+            path,
+            start: time.elapsed(),
+        };
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn intro_camera(
+    mut mode: ResMut<CameraMode>,
+    paths: Query<(&Curve, &CameraPath, &GlobalTransform)>,
+    targets: Query<&GlobalTransform, With<CameraTarget>>,
+    mut cameras: Query<(&Camera, &mut Transform)>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    touches: Res<Touches>,
+    mut contexts: EguiContexts,
+    time: Res<Time>,
+) {
+    let CameraMode::Intro { path, start } = *mode else {
+        return;
+    };
+    let Ok((curve, path, path_gt)) = paths.get(path) else {
+        *mode = CameraMode::Free;
+        return;
+    };
+    let t = (time.elapsed() - start).as_secs_f32() / path.duration;
+    let skip = keys.get_just_pressed().next().is_some()
+        || mouse.get_just_pressed().next().is_some()
+        || touches.any_just_pressed();
+    if t >= 1.0 || skip {
+        *mode = CameraMode::Free;
+        return;
+    }
+    let look = targets.iter().next().map(|gt| gt.translation());
+    for (camera, mut trans) in cameras.iter_mut() {
+        if camera.is_active {
+            trans.translation = path_gt.transform_point(curve.sample(t));
+            if let Some(look) = look {
+                trans.look_at(look, Vec3::Y);
+            }
+        }
+    }
+    egui::Area::new("intro")
+        .anchor(Align2::CENTER_BOTTOM, egui::Vec2::new(0.0, -60.0))
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(RichText::new("Press any key to skip").size(20.0));
+        });
 }
 
 fn target_camera(
     mut targets: Query<(&mut CameraTarget, &Transform)>,
     mut cameras: Query<(&Camera, &mut Transform), Without<CameraTarget>>,
+    mode: Res<CameraMode>,
+    mut trauma: ResMut<Trauma>,
     time: Res<Time>,
 ) {
+    if matches!(*mode, CameraMode::Intro { .. }) {
+        return;
+    }
+    let prev_shake = trauma.offset;
+    let shake = trauma.shake(time.elapsed_seconds(), time.delta_seconds());
     for (mut target, target_trans) in targets.iter_mut() {
         target.orbit_angle += target.orbit * time.delta_seconds();
         for (camera, mut camera_trans) in cameras.iter_mut() {
@@ -49,7 +400,7 @@ fn target_camera(
                     target.distance * rad.cos() * target.orbit_angle.sin(),
                 );
                 let (pos, vel) = smooth_damp_vec3(
-                    camera_trans.translation,
+                    camera_trans.translation - prev_shake,
                     target_trans.translation + dir,
                     target.velocity,
                     target.smooth,
@@ -57,8 +408,8 @@ fn target_camera(
                     time.delta_seconds(),
                 );
                 target.velocity = vel;
-                camera_trans.translation = pos;
-                camera_trans.look_at(pos - dir, Vec3::Y);
+                camera_trans.translation = pos + shake;
+                camera_trans.look_at(pos + shake - dir, Vec3::Y);
             }
         }
     }
@@ -69,7 +420,24 @@ pub struct CameraPlugin;
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
         app.editor_registry::<CameraTarget>()
-            .add_systems(Update, target_camera.in_set(Gameplay));
+            .editor_registry::<CameraPath>()
+            .init_resource::<CameraMode>()
+            .init_resource::<Trauma>()
+            .add_systems(
+                Update,
+                (
+                    start_intro,
+                    add_trauma,
+                    intro_camera,
+                    // Casting a spell clears the aiming, so the click must be checked before that
+                    (select_unit.before(cast_spell), control_camera)
+                        .chain()
+                        .run_if(not(in_state(Level::MainMenu))),
+                    target_camera,
+                )
+                    .chain()
+                    .in_set(Gameplay),
+            );
         #[cfg(feature = "editor")]
         app.editor_bundle(
             "Level",
@@ -79,6 +447,16 @@ impl Plugin for CameraPlugin {
                 CameraTarget::default(),
                 Name::new("Camera Target"),
             ),
+        )
+        .editor_bundle(
+            "Level",
+            "Camera Path",
+            (
+                TransformBundle::default(),
+                crate::spline::Spline,
+                CameraPath::default(),
+                Name::new("Camera Path"),
+            ),
         );
     }
 }
@@ -0,0 +1,93 @@
+//! Gameplay events, emitted by the core systems so that other systems can react.
+
+use bevy::prelude::*;
+
+use enum_iterator::Sequence;
+
+use crate::projectile::Damage;
+use crate::unit::UnitPrefab;
+
+#[derive(Event, Clone, Copy)]
+pub struct UnitSpawned {
+    /// The entity with the `Unit` (not the root of the prefab)
+    pub entity: Entity,
+    pub prefab: UnitPrefab,
+}
+
+#[derive(Event, Clone, Copy)]
+pub struct UnitDied {
+    /// The entity with the `Unit` (not the root of the prefab)
+    pub entity: Entity,
+    pub prefab: Option<UnitPrefab>,
+    pub position: Vec3,
+}
+
+#[derive(Event, Clone, Copy)]
+pub struct UnitReachedGoal {
+    /// The entity with the `Unit` (not the root of the prefab)
+    pub entity: Entity,
+    pub prefab: Option<UnitPrefab>,
+    pub position: Vec3,
+}
+
+#[derive(Event, Clone, Copy)]
+pub struct ProjectileFired {
+    pub tower: Entity,
+}
+
+#[derive(Event, Clone, Copy)]
+pub struct DamageDealt {
+    /// The tower dealing the damage, or `None` for spells
+    pub source: Option<Entity>,
+    pub target: Entity,
+    pub damage: Damage,
+    /// Health actually lost by the target
+    pub amount: f32,
+    /// The damage killed the target
+    pub lethal: bool,
+    pub position: Vec3,
+}
+
+#[derive(Event, Clone, Copy)]
+pub struct Exploded {
+    pub position: Vec3,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Sequence)]
+pub enum Upgrade {
+    Circle,
+    Speed,
+    Demon,
+    Appease,
+}
+
+impl Upgrade {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Upgrade::Circle => "Summoning circles",
+            Upgrade::Speed => "Faster summoning",
+            Upgrade::Demon => "New demons",
+            Upgrade::Appease => "Appeasements",
+        }
+    }
+}
+
+#[derive(Event, Clone, Copy)]
+pub struct UpgradePurchased {
+    pub upgrade: Upgrade,
+    pub cost: u32,
+}
+
+pub struct EventsPlugin;
+
+impl Plugin for EventsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<UnitSpawned>()
+            .add_event::<UnitDied>()
+            .add_event::<UnitReachedGoal>()
+            .add_event::<ProjectileFired>()
+            .add_event::<DamageDealt>()
+            .add_event::<Exploded>()
+            .add_event::<UpgradePurchased>();
+    }
+}
@@ -0,0 +1,64 @@
+use bevy::prelude::*;
+use bevy_egui::egui::Color32;
+use space_editor::prelude::*;
+
+use crate::level::Gameplay;
+use crate::tower::Tower;
+use crate::unit::{Goal, Unit};
+
+/// Who is fighting whom, units default to demons and towers and goals to defenders
+#[derive(Component, Reflect, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[reflect(Component, Default)]
+pub enum Faction {
+    #[default]
+    Demons,
+    Defenders,
+    Neutral,
+}
+
+impl Faction {
+    /// The faction of the player
+    pub const PLAYER: Faction = Faction::Demons;
+
+    /// Can this faction hurt (or target, or score against) the other faction
+    #[inline]
+    pub fn hostile(&self, other: Faction) -> bool {
+        matches!(
+            (self, other),
+            (Faction::Demons, Faction::Defenders) | (Faction::Defenders, Faction::Demons)
+        )
+    }
+
+    #[inline]
+    pub const fn color(&self) -> Color32 {
+        match self {
+            Faction::Demons => Color32::from_rgb(200, 20, 20),
+            Faction::Defenders => Color32::from_rgb(40, 110, 230),
+            Faction::Neutral => Color32::from_rgb(160, 160, 160),
+        }
+    }
+}
+
+/// Give entities without an explicit faction the default for their kind
+#[allow(clippy::type_complexity)]
+fn assign_factions(
+    mut commands: Commands,
+    units: Query<Entity, (With<Unit>, Without<Faction>)>,
+    defenders: Query<Entity, (Or<(With<Tower>, With<Goal>)>, Without<Faction>)>,
+) {
+    for entity in units.iter() {
+        commands.entity(entity).insert(Faction::Demons);
+    }
+    for entity in defenders.iter() {
+        commands.entity(entity).insert(Faction::Defenders);
+    }
+}
+
+pub struct FactionPlugin;
+
+impl Plugin for FactionPlugin {
+    fn build(&self, app: &mut App) {
+        app.editor_registry::<Faction>()
+            .add_systems(PreUpdate, assign_factions.in_set(Gameplay));
+    }
+}
@@ -4,6 +4,7 @@ use bevy::prelude::*;
 use space_editor::prelude::*;
 
 use crate::level::{Gameplay, LevelLocal};
+use crate::pool::{Lifetime, Pool};
 
 #[derive(Clone, Copy, Reflect, Default)]
 #[reflect(Default)]
@@ -20,6 +21,14 @@ impl FxLibrary {
             FxLibrary::Explosion => "scenes/Explosion.scn.ron",
         }
     }
+
+    /// Seconds until the effect is returned to the pool
+    pub fn lifetime(&self) -> f32 {
+        match self {
+            FxLibrary::Death => 2.0,
+            FxLibrary::Explosion => 2.5,
+        }
+    }
 }
 
 #[derive(Component, Reflect, Clone, Default)]
@@ -27,10 +36,12 @@ impl FxLibrary {
 pub struct Spawnable(pub FxLibrary);
 
 impl Spawnable {
-    pub fn spawn(&self, pos: Vec3, commands: &mut Commands) {
-        commands
-            .spawn(PrefabBundle::new(self.0.path()))
-            .insert((LevelLocal, Transform::from_translation(pos)));
+    pub fn spawn(&self, pos: Vec3, commands: &mut Commands, pool: &mut Pool) {
+        pool.spawn(commands, self.0.path()).insert((
+            LevelLocal,
+            Transform::from_translation(pos),
+            Lifetime(self.0.lifetime()),
+        ));
     }
 }
 
@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_egui::egui::{self, Align2, Color32, FontId, Id, LayerId, Order};
+use bevy_egui::{EguiContexts, EguiSettings};
+
+use crate::events::{DamageDealt, UnitDied, UnitReachedGoal};
+use crate::faction::Faction;
+use crate::level::{Gameplay, Level};
+use crate::projectile::Damage;
+use crate::settings::Settings;
+use crate::tower::{Tower, Veteran};
+use crate::unit::{Health, MaxHealth, Unit};
+
+struct FloatingNumber {
+    position: Vec3,
+    text: String,
+    color: Color32,
+    start: Duration,
+}
+
+#[derive(Resource, Default)]
+struct FloatingNumbers(Vec<FloatingNumber>);
+
+impl FloatingNumbers {
+    const LIFETIME: Duration = Duration::from_millis(1000);
+    const RISE: f32 = 1.5;
+}
+
+fn damage_color(damage: &Damage) -> Color32 {
+    match damage {
+        Damage::Physical(_) => Color32::from_rgb(240, 240, 240),
+        Damage::Magical(_) => Color32::from_rgb(90, 160, 255),
+        Damage::Explosive(..) => Color32::from_rgb(255, 150, 0),
+    }
+}
+
+fn collect_numbers(
+    mut numbers: ResMut<FloatingNumbers>,
+    mut dealt: EventReader<DamageDealt>,
+    mut died: EventReader<UnitDied>,
+    mut reached: EventReader<UnitReachedGoal>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+) {
+    let time = time.elapsed();
+    numbers
+        .0
+        .retain(|n| n.start + FloatingNumbers::LIFETIME > time);
+    for ev in dealt.read() {
+        if settings.damage_numbers && ev.amount > 0.0 {
+            numbers.0.push(FloatingNumber {
+                position: ev.position + Vec3::Y * 1.5,
+                text: format!("{:.0}", ev.amount),
+                color: damage_color(&ev.damage),
+                start: time,
+            });
+        }
+    }
+    // Every death is worth a soul, and every breach costs the defenders morale
+    for ev in died.read() {
+        if settings.damage_numbers {
+            numbers.0.push(FloatingNumber {
+                position: ev.position + Vec3::Y * 2.0,
+                text: "+1 soul".to_string(),
+                color: Color32::from_rgb(200, 80, 255),
+                start: time,
+            });
+        }
+    }
+    for ev in reached.read() {
+        if settings.damage_numbers {
+            numbers.0.push(FloatingNumber {
+                position: ev.position + Vec3::Y * 2.0,
+                text: "Breach!".to_string(),
+                color: Color32::RED,
+                start: time,
+            });
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_hud(
+    mut contexts: EguiContexts,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    units: Query<(&Health, &MaxHealth, &GlobalTransform, &Faction), With<Unit>>,
+    towers: Query<(&Tower, &GlobalTransform, &Faction, Option<&Veteran>)>,
+    numbers: Res<FloatingNumbers>,
+    settings: Res<Settings>,
+    egui_settings: Res<EguiSettings>,
+    time: Res<Time>,
+) {
+    let Some((camera, camera_gt)) = cameras.iter().find(|(c, _)| c.is_active) else {
+        return;
+    };
+    let to_screen = |pos: Vec3| {
+        camera
+            .world_to_viewport(camera_gt, pos)
+            .map(|p| egui::pos2(p.x, p.y) / egui_settings.scale_factor)
+    };
+    let painter = contexts
+        .ctx_mut()
+        .layer_painter(LayerId::new(Order::Background, Id::new("hud")));
+    if settings.health_bars {
+        let bar = |pos: Vec3, fraction: f32, width: f32, color: Color32| {
+            if let Some(pos) = to_screen(pos) {
+                let size = egui::vec2(width, 4.0);
+                let rect = egui::Rect::from_center_size(pos, size);
+                let mut fill = rect;
+                fill.set_width(size.x * fraction.clamp(0.0, 1.0));
+                painter.rect_filled(rect.expand(1.0), 1.0, Color32::BLACK);
+                painter.rect_filled(fill, 1.0, color);
+            }
+        };
+        for (health, max, gt, faction) in units.iter() {
+            if health.0 < max.0 {
+                let pos = gt.translation() + Vec3::Y * 2.0;
+                bar(pos, health.0 / max.0, 30.0, faction.color());
+            }
+        }
+        for (tower, gt, faction, _) in towers.iter() {
+            if tower.health_fraction() < 1.0 {
+                let pos = gt.translation() + gt.up() * (tower.height() + 1.5);
+                bar(pos, tower.health_fraction(), 50.0, faction.color());
+            }
+        }
+    }
+    for (tower, gt, _, veteran) in towers.iter() {
+        let Some(veteran) = veteran.filter(|v| v.level() > 0) else {
+            continue;
+        };
+        if let Some(pos) = to_screen(gt.translation() + gt.up() * (tower.height() + 2.5)) {
+            let text = if veteran.level() == Veteran::MAX_LEVEL {
+                "Lv MAX".to_string()
+            } else {
+                format!("Lv {}", veteran.level())
+            };
+            painter.text(
+                pos,
+                Align2::CENTER_CENTER,
+                text,
+                FontId::proportional(16.0),
+                Color32::GOLD,
+            );
+        }
+    }
+    if settings.damage_numbers {
+        let time = time.elapsed();
+        for n in numbers.0.iter() {
+            let t = (time - n.start).as_secs_f32() / FloatingNumbers::LIFETIME.as_secs_f32();
+            if let Some(pos) = to_screen(n.position + Vec3::Y * FloatingNumbers::RISE * t) {
+                painter.text(
+                    pos,
+                    Align2::CENTER_CENTER,
+                    &n.text,
+                    FontId::proportional(16.0),
+                    n.color.gamma_multiply(1.0 - t),
+                );
+            }
+        }
+    }
+}
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FloatingNumbers>().add_systems(
+            Update,
+            (collect_numbers, draw_hud)
+                .chain()
+                .in_set(Gameplay)
+                .run_if(not(in_state(Level::MainMenu))),
+        );
+    }
+}
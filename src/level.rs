@@ -5,6 +5,9 @@ use bevy::prelude::*;
 use enum_iterator::{all, Sequence};
 use space_editor::prelude::*;
 
+use crate::pool::{Benchmark, Pool};
+use crate::spells::Spells;
+use crate::stats::Statistics;
 use crate::unit::Spawner;
 
 #[derive(Component, Clone, Copy)]
@@ -45,10 +48,15 @@ impl Level {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn load_level(
     mut commands: Commands,
     level: Res<State<Level>>,
     mut stats: ResMut<GameStats>,
+    mut statistics: ResMut<Statistics>,
+    mut pool: ResMut<Pool>,
+    mut benchmark: ResMut<Benchmark>,
+    mut spells: ResMut<Spells>,
     time: Res<Time>,
 ) {
     match level.get() {
@@ -72,6 +80,10 @@ fn load_level(
     };
     *stats = GameStats::default();
     stats.start_time = time.elapsed();
+    *statistics = Statistics::default();
+    pool.clear();
+    benchmark.reset();
+    *spells = Spells::default();
     commands.spawn((LevelLocal, Spawner::default()));
 }
 
@@ -126,6 +138,14 @@ impl GameStats {
     pub fn time_limit(&self) -> Duration {
         self.start_time + Self::TIME_LIMIT + Self::APPEASEMENT * self.upgrade_appease as u32
     }
+
+    pub fn victory(&self) -> bool {
+        self.defender_morale == 0
+    }
+
+    pub fn defeat(&self, time: Duration) -> bool {
+        !self.victory() && self.time_limit() < time
+    }
 }
 
 #[derive(SystemSet, Hash, Debug, PartialEq, Eq, Clone, Copy)]
@@ -1,9 +1,18 @@
 mod audio;
 mod camera;
+mod events;
+mod faction;
 mod fx;
+mod hud;
 mod level;
+mod minimap;
+mod pool;
 mod projectile;
+mod settings;
+mod sight;
+mod spells;
 mod spline;
+mod stats;
 mod tower;
 mod ui;
 mod unit;
@@ -14,10 +23,19 @@ use space_editor::prelude::*;
 
 use audio::AudioPlugin;
 use camera::CameraPlugin;
+use events::EventsPlugin;
+use faction::FactionPlugin;
 use fx::FxPlugin;
+use hud::HudPlugin;
 use level::{Level, LevelPlugin};
+use minimap::MinimapPlugin;
+use pool::PoolPlugin;
 use projectile::ProjectilePlugin;
+use settings::SettingsPlugin;
+use sight::SightPlugin;
+use spells::SpellsPlugin;
 use spline::SplinePlugin;
+use stats::StatsPlugin;
 use tower::TowerPlugin;
 use ui::UiPlugin;
 use unit::UnitPlugin;
@@ -39,6 +57,17 @@ fn main() {
         AudioPlugin,
         UiPlugin,
         FxPlugin,
+    ))
+    .add_plugins((
+        EventsPlugin,
+        SettingsPlugin,
+        StatsPlugin,
+        HudPlugin,
+        MinimapPlugin,
+        SpellsPlugin,
+        PoolPlugin,
+        SightPlugin,
+        FactionPlugin,
     ));
     #[cfg(feature = "editor")]
     app.add_systems(Startup, space_editor::space_editor_ui::simple_editor_setup);
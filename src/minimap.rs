@@ -0,0 +1,204 @@
+use bevy::prelude::*;
+use bevy_egui::egui::{self, Align2, Color32, Pos2, Sense, Shape, Stroke};
+use bevy_egui::EguiContexts;
+
+use crate::camera::{CameraMode, CameraPath, CameraTarget};
+use crate::faction::Faction;
+use crate::level::{Gameplay, Level};
+use crate::settings::Settings;
+use crate::spline::Curve;
+use crate::tower::{FrostAura, HolyAura, Revealer, Support, Supported, Targetter, Tower, Veteran};
+use crate::unit::{Goal, Summoned, Unit, UnitPrefab};
+
+const SIZE: f32 = 200.0;
+const CURVE_SAMPLES: usize = 64;
+
+fn unit_color(prefab: Option<UnitPrefab>, faction: Faction) -> Color32 {
+    if faction != Faction::Demons {
+        return faction.color();
+    }
+    match prefab {
+        Some(UnitPrefab::Imp) => Color32::from_rgb(255, 40, 60),
+        Some(UnitPrefab::Ghoul) => Color32::from_rgb(120, 220, 80),
+        Some(UnitPrefab::Hellhound) => Color32::from_rgb(255, 150, 0),
+        Some(UnitPrefab::DarkKnight) => Color32::from_rgb(170, 60, 255),
+        Some(UnitPrefab::Shade) => Color32::from_rgb(110, 100, 140),
+        None => Color32::WHITE,
+    }
+}
+
+/// Maps between the ground plane and the minimap
+struct MapTransform {
+    center: Vec2,
+    scale: f32,
+    rect: egui::Rect,
+}
+
+impl MapTransform {
+    fn new(min: Vec2, max: Vec2, rect: egui::Rect) -> Self {
+        let extent = (max - min).max_element().max(1.0) * 1.1;
+        Self {
+            center: (min + max) * 0.5,
+            scale: rect.width() / extent,
+            rect,
+        }
+    }
+
+    fn to_map(&self, pos: Vec3) -> Pos2 {
+        let p = (Vec2::new(pos.x, pos.z) - self.center) * self.scale;
+        self.rect.center() + egui::vec2(p.x, p.y)
+    }
+
+    fn to_world(&self, pos: Pos2) -> Vec2 {
+        let p = pos - self.rect.center();
+        Vec2::new(p.x, p.y) / self.scale + self.center
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn minimap(
+    mut contexts: EguiContexts,
+    curves: Query<(&Curve, &GlobalTransform), Without<CameraPath>>,
+    towers: Query<
+        (
+            &GlobalTransform,
+            &Faction,
+            Option<&Targetter>,
+            Option<&Supported>,
+            Option<&Veteran>,
+            Option<&FrostAura>,
+            Option<&HolyAura>,
+            Option<&Support>,
+            Option<&Revealer>,
+        ),
+        With<Tower>,
+    >,
+    goals: Query<(&GlobalTransform, &Goal)>,
+    units: Query<(&GlobalTransform, Option<&Summoned>, &Faction), With<Unit>>,
+    mut targets: Query<(&mut CameraTarget, &mut Transform)>,
+    mut mode: ResMut<CameraMode>,
+    settings: Res<Settings>,
+) {
+    if !settings.minimap {
+        return;
+    }
+    let paths: Vec<Vec<Vec3>> = curves
+        .iter()
+        .map(|(c, gt)| {
+            (0..=CURVE_SAMPLES)
+                .map(|i| gt.transform_point(c.sample(i as f32 / CURVE_SAMPLES as f32)))
+                .collect()
+        })
+        .collect();
+    let (min, max) = paths
+        .iter()
+        .flatten()
+        .copied()
+        .chain(towers.iter().map(|(gt, ..)| gt.translation()))
+        .chain(goals.iter().map(|(gt, _)| gt.translation()))
+        .fold((Vec2::MAX, Vec2::MIN), |(min, max), p| {
+            let p = Vec2::new(p.x, p.z);
+            (min.min(p), max.max(p))
+        });
+    if min.x > max.x {
+        return;
+    }
+
+    egui::Area::new("minimap")
+        .anchor(Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -40.0))
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Frame::canvas(ui.style()).show(ui, |ui| {
+                let (response, painter) =
+                    ui.allocate_painter(egui::vec2(SIZE, SIZE), Sense::click_and_drag());
+                let map = MapTransform::new(min, max, response.rect);
+
+                for path in paths.iter() {
+                    painter.add(Shape::line(
+                        path.iter().map(|&p| map.to_map(p)).collect(),
+                        Stroke::new(3.0, Color32::from_gray(140)),
+                    ));
+                }
+                for (gt, goal) in goals.iter() {
+                    painter.circle_filled(
+                        map.to_map(gt.translation()),
+                        goal.radius() * map.scale,
+                        Color32::from_rgb(0, 160, 0),
+                    );
+                }
+                for (gt, faction, targetter, supported, veteran, frost, holy, support, revealer) in
+                    towers.iter()
+                {
+                    let pos = map.to_map(gt.translation());
+                    let ring = |range: f32, (r, g, b)| {
+                        painter.circle_stroke(
+                            pos,
+                            range * map.scale,
+                            Stroke::new(1.0, Color32::from_rgba_unmultiplied(r, g, b, 60)),
+                        );
+                    };
+                    if let Some(targetter) = targetter {
+                        ring(targetter.range(supported, veteran), (255, 255, 255));
+                    }
+                    if let Some(aura) = frost {
+                        ring(aura.range(veteran), (128, 204, 255));
+                    }
+                    if let Some(aura) = holy {
+                        ring(aura.range(veteran), (255, 215, 0));
+                    }
+                    if let Some(support) = support {
+                        ring(support.range(veteran), (50, 205, 50));
+                    }
+                    if let Some(revealer) = revealer {
+                        ring(revealer.range(veteran), (200, 200, 200));
+                    }
+                    painter.rect_filled(
+                        egui::Rect::from_center_size(pos, egui::vec2(6.0, 6.0)),
+                        0.0,
+                        faction.color(),
+                    );
+                }
+                for (gt, summoned, faction) in units.iter() {
+                    painter.circle_filled(
+                        map.to_map(gt.translation()),
+                        2.5,
+                        unit_color(summoned.map(|s| s.0), *faction),
+                    );
+                }
+                for (_, trans) in targets.iter() {
+                    let pos = map.to_map(trans.translation);
+                    let stroke = Stroke::new(1.5, Color32::YELLOW);
+                    painter.line_segment(
+                        [pos - egui::vec2(5.0, 0.0), pos + egui::vec2(5.0, 0.0)],
+                        stroke,
+                    );
+                    painter.line_segment(
+                        [pos - egui::vec2(0.0, 5.0), pos + egui::vec2(0.0, 5.0)],
+                        stroke,
+                    );
+                }
+
+                if let Some(pointer) = response.interact_pointer_pos() {
+                    if response.clicked() || response.dragged() {
+                        let pos = map.to_world(pointer);
+                        for (mut target, mut trans) in targets.iter_mut() {
+                            target.move_to(&mut trans, pos);
+                        }
+                        *mode = CameraMode::Free;
+                    }
+                }
+            });
+        });
+}
+
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            minimap
+                .in_set(Gameplay)
+                .run_if(not(in_state(Level::MainMenu))),
+        );
+    }
+}
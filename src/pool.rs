@@ -0,0 +1,260 @@
+use bevy::audio::{AudioSink, Volume};
+use bevy::ecs::system::{EntityCommands, SystemParam};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use space_editor::prelude::*;
+
+use crate::fx::{FxLibrary, Spawnable};
+use crate::level::Gameplay;
+use crate::settings::Settings;
+use crate::spline::follow_curve;
+
+/// Recycles hidden prefab instances instead of loading the scenes again
+#[derive(Resource, Default)]
+pub struct Pool {
+    free: HashMap<&'static str, Vec<Entity>>,
+    enabled: bool,
+    pub hits: u32,
+    pub misses: u32,
+}
+
+/// Prefab instances that can be returned to the pool
+#[derive(Component, Clone, Copy)]
+pub struct Pooled(&'static str);
+
+/// Pooled instances waiting to be reused
+#[derive(Component, Clone, Copy)]
+pub struct Released;
+
+/// Pooled instances that have just been taken from the pool, hidden until they have been placed
+#[derive(Component, Clone, Copy)]
+struct Reused;
+
+/// Return the instance to the pool after some seconds
+#[derive(Component, Clone, Copy)]
+pub struct Lifetime(pub f32);
+
+impl Pool {
+    /// Take an instance of the prefab from the pool, or load a new one
+    pub fn spawn<'a>(
+        &mut self,
+        commands: &'a mut Commands,
+        path: &'static str,
+    ) -> EntityCommands<'a> {
+        if self.enabled {
+            let free = self.free.entry(path).or_default();
+            while let Some(entity) = free.pop() {
+                if commands.get_entity(entity).is_some() {
+                    self.hits += 1;
+                    let mut instance = commands.entity(entity);
+                    instance.remove::<Released>().insert(Reused);
+                    return instance;
+                }
+            }
+        }
+        self.misses += 1;
+        commands.spawn((PrefabBundle::new(path), Pooled(path)))
+    }
+
+    /// Hide the instance until it is needed again (or despawn it if pooling is disabled).
+    /// Returns true if the instance was kept.
+    pub fn release(&mut self, commands: &mut Commands, entity: Entity, pooled: &Pooled) -> bool {
+        if self.enabled {
+            let free = self.free.entry(pooled.0).or_default();
+            if free.contains(&entity) {
+                return true;
+            }
+            free.push(entity);
+            commands
+                .entity(entity)
+                .remove::<Lifetime>()
+                .insert((Visibility::Hidden, Released));
+        } else {
+            commands.entity(entity).despawn_recursive();
+        }
+        self.enabled
+    }
+
+    pub fn clear(&mut self) {
+        self.free.clear();
+        self.hits = 0;
+        self.misses = 0;
+    }
+}
+
+/// Removes the contents of prefab instances, returning the instances to the pool
+#[derive(SystemParam)]
+pub struct Recycler<'w, 's> {
+    pool: ResMut<'w, Pool>,
+    parents: Query<'w, 's, &'static Parent>,
+    pooled: Query<'w, 's, &'static Pooled>,
+}
+
+impl Recycler<'_, '_> {
+    /// Release the instance that the entity belongs to, or despawn the entity if it is not pooled.
+    /// Returns true if the entity was kept (and should be reset).
+    pub fn recycle(&mut self, commands: &mut Commands, entity: Entity) -> bool {
+        let root = self.parents.get(entity).map_or(entity, |p| p.get());
+        if let Ok(pooled) = self.pooled.get(root) {
+            self.pool.release(commands, root, pooled)
+        } else {
+            commands.entity(entity).despawn_recursive();
+            false
+        }
+    }
+
+    pub fn pool(&mut self) -> &mut Pool {
+        &mut self.pool
+    }
+}
+
+fn expire(
+    mut commands: Commands,
+    mut pool: ResMut<Pool>,
+    mut q: Query<(Entity, &mut Lifetime, &Pooled)>,
+    time: Res<Time>,
+) {
+    for (entity, mut lifetime, pooled) in q.iter_mut() {
+        lifetime.0 -= time.delta_seconds();
+        if lifetime.0 <= 0.0 {
+            pool.release(&mut commands, entity, pooled);
+        }
+    }
+}
+
+/// Show reused instances and play their sounds again
+fn reawaken(
+    mut commands: Commands,
+    mut q: Query<(Entity, &mut Visibility), With<Reused>>,
+    children: Query<&Children>,
+    sinks: Query<(), With<AudioSink>>,
+) {
+    for (entity, mut visibility) in q.iter_mut() {
+        *visibility = Visibility::Inherited;
+        commands.entity(entity).remove::<Reused>();
+        for child in children.iter_descendants(entity) {
+            if sinks.contains(child) {
+                commands.entity(child).remove::<AudioSink>();
+            }
+        }
+    }
+}
+
+/// Spawns a fixed load of effects, first with pooling and then without, to compare frame times
+#[derive(Resource, Default)]
+pub struct Benchmark {
+    /// Seconds since the start, or None when not running
+    elapsed: Option<f32>,
+    frames: [u32; 2],
+    seconds: [f32; 2],
+    /// The volume to restore once the benchmark is over
+    volume: Option<Volume>,
+    /// Average frame times in milliseconds, with and without pooling
+    pub result: Option<(f32, f32)>,
+}
+
+impl Benchmark {
+    /// Effects spawned every frame
+    const LOAD: usize = 3;
+    /// Seconds of spawning with pooling, and then without
+    const DURATION: f32 = 5.0;
+    /// Seconds to wait for the last effects before unmuting the sounds
+    const SETTLE: f32 = 3.0;
+
+    pub fn start(&mut self) {
+        if !self.running() {
+            *self = Self {
+                elapsed: Some(0.0),
+                volume: self.volume.take(),
+                ..Default::default()
+            };
+        }
+    }
+
+    /// Stop the benchmark and forget the results (the sounds are unmuted on the next frame)
+    pub fn reset(&mut self) {
+        *self = Self {
+            volume: self.volume.take(),
+            ..Default::default()
+        };
+    }
+
+    #[inline]
+    pub fn running(&self) -> bool {
+        self.elapsed.is_some()
+    }
+
+    /// Fraction of the benchmark that is done
+    pub fn progress(&self) -> f32 {
+        self.elapsed
+            .map_or(0.0, |e| e / (Self::DURATION * 2.0 + Self::SETTLE))
+    }
+}
+
+fn sync_pool_setting(mut pool: ResMut<Pool>, settings: Res<Settings>) {
+    pool.enabled = settings.pooling;
+}
+
+fn benchmark(
+    mut commands: Commands,
+    mut pool: ResMut<Pool>,
+    mut bench: ResMut<Benchmark>,
+    mut volume: ResMut<GlobalVolume>,
+    time: Res<Time>,
+) {
+    let Some(elapsed) = bench.elapsed else {
+        if let Some(v) = bench.volume.take() {
+            volume.volume = v;
+        }
+        return;
+    };
+    // Mute the sounds started during the benchmark
+    if bench.volume.is_none() {
+        bench.volume = Some(volume.volume);
+        volume.volume = Volume::new(0.0);
+    }
+    let run = (elapsed / Benchmark::DURATION) as usize;
+    if run < 2 {
+        pool.enabled = run == 0;
+        if elapsed > 0.0 {
+            bench.frames[run] += 1;
+            bench.seconds[run] += time.delta_seconds();
+        }
+        for i in 0..Benchmark::LOAD {
+            let fx = if i % 2 == 0 {
+                FxLibrary::Death
+            } else {
+                FxLibrary::Explosion
+            };
+            // Below the ground, out of sight
+            Spawnable(fx).spawn(Vec3::NEG_Y * 100.0, &mut commands, &mut pool);
+        }
+    } else if elapsed > Benchmark::DURATION * 2.0 + Benchmark::SETTLE {
+        let average = |run: usize| bench.seconds[run] * 1000.0 / bench.frames[run].max(1) as f32;
+        bench.result = Some((average(0), average(1)));
+        bench.elapsed = None;
+        return;
+    }
+    bench.elapsed = Some(elapsed + time.delta_seconds());
+}
+
+pub struct PoolPlugin;
+
+impl Plugin for PoolPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Pool>()
+            .init_resource::<Benchmark>()
+            .add_systems(
+                Update,
+                (
+                    sync_pool_setting,
+                    benchmark,
+                    expire,
+                    // Reused units are woken in `PreUpdate` and placed on their curve
+                    reawaken.after(follow_curve),
+                )
+                    .chain()
+                    .in_set(Gameplay),
+            );
+    }
+}
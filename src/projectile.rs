@@ -1,10 +1,15 @@
 use std::time::Duration;
 
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use space_editor::prelude::*;
 
-use crate::fx::{DespawnTimer, Spawnable};
-use crate::level::Gameplay;
+use crate::events::{DamageDealt, Exploded};
+use crate::faction::Faction;
+use crate::fx::{DespawnTimer, FxLibrary, Spawnable};
+use crate::level::{Gameplay, LevelLocal};
+use crate::pool::Recycler;
+use crate::spline::{Curve, FollowCurve};
 use crate::unit::Health;
 
 #[derive(Reflect, Clone, Copy, PartialEq)]
@@ -12,7 +17,8 @@ use crate::unit::Health;
 pub enum Damage {
     Physical(f32),
     Magical(f32),
-    Explosive(f32, f32),
+    /// Damage, radius and how the damage decreases towards the edge
+    Explosive(f32, f32, Falloff),
 }
 
 impl Default for Damage {
@@ -21,21 +27,88 @@ impl Default for Damage {
     }
 }
 
+impl Damage {
+    pub fn scaled(&self, factor: f32) -> Self {
+        match *self {
+            Damage::Physical(d) => Damage::Physical(d * factor),
+            Damage::Magical(d) => Damage::Magical(d * factor),
+            Damage::Explosive(d, r, f) => Damage::Explosive(d * factor, r, f),
+        }
+    }
+}
+
+#[derive(Reflect, Clone, Copy, PartialEq, Default)]
+#[reflect(Default)]
+pub enum Falloff {
+    #[default]
+    None,
+    Linear,
+    Quadratic,
+}
+
+impl Falloff {
+    /// Fraction of the damage dealt at a distance from the centre
+    pub fn factor(&self, distance: f32, radius: f32) -> f32 {
+        let t = (1.0 - distance / radius).clamp(0.0, 1.0);
+        match self {
+            Falloff::None => 1.0,
+            Falloff::Linear => t,
+            Falloff::Quadratic => t * t,
+        }
+    }
+}
+
+/// Units that can be hit by projectiles
+type Targets<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static GlobalTransform,
+        &'static mut Health,
+        Option<&'static mut FollowCurve>,
+        &'static Faction,
+    ),
+>;
+
 #[derive(Component, Clone)]
 pub struct ProjectileTarget {
     entity: Entity,
     target: Vec3,
     pos: Vec3,
+    source: Entity,
+    /// How much to lead the target, see `Tower`
+    aim: f32,
+    /// Only units hostile to this faction are hurt
+    faction: Faction,
+    /// Damage multiplier from the level of the tower
+    power: f32,
 }
 
 impl ProjectileTarget {
-    pub fn new(entity: Entity, target: Vec3, pos: Vec3) -> Self {
+    pub fn new(
+        entity: Entity,
+        target: Vec3,
+        pos: Vec3,
+        source: Entity,
+        aim: f32,
+        faction: Faction,
+    ) -> Self {
         Self {
             entity,
             target,
             pos,
+            source,
+            aim,
+            faction,
+            power: 1.0,
         }
     }
+
+    pub fn with_power(mut self, power: f32) -> Self {
+        self.power = power;
+        self
+    }
 }
 
 #[allow(clippy::type_complexity)]
@@ -68,11 +141,27 @@ fn setup_projectile(
     }
 }
 
+/// Clear what reused projectiles remember from their previous flight
+fn reset_projectile(
+    mut q: Query<(Option<&mut DumbProjectile>, Option<&mut Pierce>), Added<ProjectileTarget>>,
+) {
+    for (dumb, pierce) in q.iter_mut() {
+        if let Some(mut dumb) = dumb {
+            dumb.velocity = None;
+        }
+        if let Some(mut pierce) = pierce {
+            pierce.hit.clear();
+        }
+    }
+}
+
 #[derive(Component, Reflect, Clone)]
 #[reflect(Component, Default)]
 pub struct HomingProjectile {
     damage: Damage,
     speed: f32,
+    /// How far to look for a new target if the old one is gone
+    retarget: f32,
 }
 
 impl Default for HomingProjectile {
@@ -80,10 +169,24 @@ impl Default for HomingProjectile {
         Self {
             damage: Damage::Physical(10.0),
             speed: 20.0,
+            retarget: 8.0,
         }
     }
 }
 
+/// Hit any unit in the way, not only the target
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component, Default)]
+pub struct Collider {
+    radius: f32,
+}
+
+impl Default for Collider {
+    fn default() -> Self {
+        Self { radius: 0.5 }
+    }
+}
+
 #[derive(Component, Reflect, Clone)]
 #[reflect(Component, Default)]
 pub struct RayProjectile {
@@ -102,19 +205,75 @@ impl Default for RayProjectile {
     }
 }
 
+/// Flies on a ballistic arc to where the target is expected to be
 #[derive(Component, Reflect, Clone)]
 #[reflect(Component, Default)]
 pub struct DumbProjectile {
     damage: Damage,
+    /// Horizontal speed, ignored if the `apex` is set
     speed: f32,
+    gravity: f32,
+    /// Height of the arc above the highest end, zero to use the `speed` instead
+    apex: f32,
+    #[reflect(ignore)]
+    velocity: Option<Vec3>,
+    #[reflect(ignore)]
+    remaining: f32,
 }
 
 impl Default for DumbProjectile {
     fn default() -> Self {
         Self {
-            damage: Damage::Explosive(10.0, 4.0),
+            damage: Damage::Explosive(10.0, 4.0, Falloff::Linear),
             speed: 20.0,
+            gravity: 0.0,
+            apex: 0.0,
+            velocity: None,
+            remaining: 0.0,
+        }
+    }
+}
+
+impl DumbProjectile {
+    fn flight_time(&self, from: Vec3, to: Vec3) -> f32 {
+        let delta = to - from;
+        let time = if self.gravity <= 0.0 {
+            delta.length() / self.speed
+        } else if self.apex > 0.0 {
+            let height = self.apex + delta.y.max(0.0);
+            (2.0 * height / self.gravity).sqrt() + (2.0 * (height - delta.y) / self.gravity).sqrt()
+        } else {
+            Vec2::new(delta.x, delta.z).length() / self.speed
+        };
+        time.max(0.01)
+    }
+
+    /// Aim at where the target will be when the projectile lands
+    fn launch(
+        &mut self,
+        from: Vec3,
+        target: &ProjectileTarget,
+        follow: Option<&FollowCurve>,
+        curves: &Query<(&Curve, &GlobalTransform)>,
+    ) {
+        let mut to = target.target;
+        let mut time = self.flight_time(from, to);
+        if let Some(follow) = follow {
+            if let Ok((curve, gt)) = curves.get(follow.curve()) {
+                for _ in 0..3 {
+                    let predicted = follow.predict(curve, gt, time);
+                    to = target.target.lerp(predicted, target.aim);
+                    time = self.flight_time(from, to);
+                }
+            }
         }
+        let delta = to + Vec3::Y * 0.3 - from;
+        self.velocity = Some(Vec3::new(
+            delta.x / time,
+            delta.y / time + 0.5 * self.gravity * time,
+            delta.z / time,
+        ));
+        self.remaining = time;
     }
 }
 
@@ -124,6 +283,12 @@ pub enum ProjectilePrefab {
     #[default]
     Arrow,
     Bomb,
+    /// Chains to nearby units
+    Bolt,
+    /// Passes through units along its line
+    Javelin,
+    /// Scatters arrows on impact
+    Cluster,
 }
 
 impl ProjectilePrefab {
@@ -131,98 +296,439 @@ impl ProjectilePrefab {
         match self {
             ProjectilePrefab::Arrow => "scenes/Arrow.scn.ron",
             ProjectilePrefab::Bomb => "scenes/Bomb.scn.ron",
+            ProjectilePrefab::Bolt => "scenes/Bolt.scn.ron",
+            ProjectilePrefab::Javelin => "scenes/Javelin.scn.ron",
+            ProjectilePrefab::Cluster => "scenes/Cluster.scn.ron",
+        }
+    }
+}
+
+/// Jump to nearby units after a hit, with decreasing damage
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component, Default)]
+pub struct Chain {
+    jumps: u8,
+    range: f32,
+    /// Fraction of the damage kept on each jump
+    decay: f32,
+    color: Color,
+}
+
+impl Default for Chain {
+    fn default() -> Self {
+        Self {
+            jumps: 3,
+            range: 4.0,
+            decay: 0.6,
+            color: Color::rgb(0.6, 0.8, 1.0),
+        }
+    }
+}
+
+/// Continue through units towards the next one along the line
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component, Default)]
+pub struct Pierce {
+    count: u8,
+    range: f32,
+    #[reflect(ignore)]
+    hit: Vec<Entity>,
+}
+
+impl Default for Pierce {
+    fn default() -> Self {
+        Self {
+            count: 2,
+            range: 8.0,
+            hit: Vec::new(),
         }
     }
 }
 
+/// Fire new projectiles at nearby units on impact
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component, Default)]
+pub struct Split {
+    count: u8,
+    range: f32,
+    projectile: ProjectilePrefab,
+}
+
+impl Default for Split {
+    fn default() -> Self {
+        Self {
+            count: 3,
+            range: 6.0,
+            projectile: ProjectilePrefab::Arrow,
+        }
+    }
+}
+
+/// A short lived line, for showing chain jumps
+#[derive(Component, Clone, Copy)]
+struct Zap {
+    from: Vec3,
+    to: Vec3,
+    color: Color,
+}
+
+impl Zap {
+    const DURATION: Duration = Duration::from_millis(200);
+}
+
+#[allow(clippy::type_complexity)]
 fn shoot_dumb(
-    mut commands: Commands,
     mut q: Query<(
         Entity,
-        &DumbProjectile,
-        &ProjectileTarget,
+        &mut DumbProjectile,
+        &mut ProjectileTarget,
         &mut Transform,
-        Option<&Spawnable>,
+        Option<&Collider>,
     )>,
-    mut targets: Query<(&GlobalTransform, &mut Health)>,
-    time: Res<Time>,
+    curves: Query<(&Curve, &GlobalTransform)>,
+    mut impacts: Impacts,
 ) {
-    for (entity, proj, target, mut trans, spawnable) in q.iter_mut() {
-        let delta = target.target + Vec3::Y * 0.3 - trans.translation;
-        let speed = proj.speed * time.delta_seconds();
-        let len2 = delta.length_squared();
-        if len2 < speed * speed {
-            deal_damage(trans.translation, proj.damage, target.entity, &mut targets);
-            commands.get_entity(entity).unwrap().despawn_recursive();
-            if let Some(spawnable) = spawnable {
-                spawnable.spawn(trans.translation, &mut commands);
+    let delta = impacts.time.delta_seconds();
+    for (entity, mut proj, mut target, mut trans, collider) in q.iter_mut() {
+        if proj.velocity.is_none() {
+            let follow = impacts.targets.get(target.entity).ok().and_then(|t| t.3);
+            proj.launch(trans.translation, &target, follow, &curves);
+        }
+        let Some(mut velocity) = proj.velocity else {
+            continue;
+        };
+        proj.remaining -= delta;
+        if proj.remaining <= 0.0 {
+            let dir = velocity.normalize_or_zero();
+            if !impacts.impact(entity, trans.translation, proj.damage, &mut target, dir) {
+                proj.velocity = None;
             }
         } else {
-            trans.translation += delta * (speed / len2.sqrt());
-            trans.look_to(delta, Vec3::Y);
+            velocity.y -= proj.gravity * delta;
+            proj.velocity = Some(velocity);
+            let next = trans.translation + velocity * delta;
+            if let Some(unit) = collider.and_then(|c| {
+                impacts.collide(entity, trans.translation, next, c.radius, target.faction)
+            }) {
+                target.entity = unit;
+                let dir = velocity.normalize_or_zero();
+                if !impacts.impact(entity, trans.translation, proj.damage, &mut target, dir) {
+                    proj.velocity = None;
+                }
+                continue;
+            }
+            trans.translation = next;
+            trans.look_to(velocity, Vec3::Y);
         }
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn shoot_homing(
-    mut commands: Commands,
     mut q: Query<(
         Entity,
         &HomingProjectile,
-        &ProjectileTarget,
+        &mut ProjectileTarget,
         &mut Transform,
-        Option<&Spawnable>,
+        Option<&Collider>,
     )>,
-    mut targets: Query<(&GlobalTransform, &mut Health)>,
-    time: Res<Time>,
+    mut impacts: Impacts,
 ) {
-    for (entity, proj, target, mut trans, spawnable) in q.iter_mut() {
-        if let Ok((gt, _)) = targets.get(target.entity) {
+    for (entity, proj, mut target, mut trans, collider) in q.iter_mut() {
+        if let Ok((_, gt, ..)) = impacts.targets.get(target.entity) {
             let delta = gt.translation() + Vec3::Y * 0.3 - trans.translation;
-            let speed = proj.speed * time.delta_seconds();
+            let speed = proj.speed * impacts.time.delta_seconds();
             let len2 = delta.length_squared();
+            let dir = delta.normalize_or_zero();
             if len2 < speed * speed {
-                deal_damage(trans.translation, proj.damage, target.entity, &mut targets);
-                commands.get_entity(entity).unwrap().despawn_recursive();
-                if let Some(spawnable) = spawnable {
-                    spawnable.spawn(trans.translation, &mut commands);
-                }
+                impacts.impact(entity, trans.translation, proj.damage, &mut target, dir);
+                continue;
+            }
+            let next = trans.translation + delta * (speed / len2.sqrt());
+            if let Some(unit) = collider.and_then(|c| {
+                impacts.collide(entity, trans.translation, next, c.radius, target.faction)
+            }) {
+                target.entity = unit;
+                impacts.impact(entity, trans.translation, proj.damage, &mut target, dir);
             } else {
-                trans.translation += delta * (speed / len2.sqrt());
+                trans.translation = next;
                 trans.look_to(delta, Vec3::Y);
             }
         } else {
-            commands.get_entity(entity).unwrap().despawn_recursive();
+            // The target is gone, find a new one nearby
+            let hit = impacts.already_hit(entity);
+            let near = units_near(
+                &impacts.targets,
+                trans.translation,
+                proj.retarget,
+                &hit,
+                target.faction,
+            );
+            if let Some(&(unit, pos, _)) = near.first() {
+                target.entity = unit;
+                target.target = pos;
+            } else {
+                impacts.remove(entity);
+            }
         }
     }
 }
 
-fn deal_damage(
+/// Units hostile to the faction within range of a position, closest first
+fn units_near(
+    targets: &Targets,
     pos: Vec3,
-    damage: Damage,
-    target: Entity,
-    targets: &mut Query<(&GlobalTransform, &mut Health)>,
-) {
-    match damage {
-        Damage::Physical(d) => {
-            if let Ok((_, mut health)) = targets.get_mut(target) {
-                health.0 -= d;
-            }
+    range: f32,
+    exclude: &[Entity],
+    faction: Faction,
+) -> Vec<(Entity, Vec3, f32)> {
+    let mut near: Vec<_> = targets
+        .iter()
+        .filter(|(e, .., f)| !exclude.contains(e) && faction.hostile(**f))
+        .map(|(e, gt, ..)| (e, gt.translation(), gt.translation().distance(pos)))
+        .filter(|(.., d)| *d < range)
+        .collect();
+    near.sort_by(|a, b| a.2.total_cmp(&b.2));
+    near
+}
+
+/// Everything needed for projectiles to hit units
+#[allow(clippy::type_complexity)]
+#[derive(SystemParam)]
+struct Impacts<'w, 's> {
+    commands: Commands<'w, 's>,
+    targets: Targets<'w, 's>,
+    modifiers: Query<
+        'w,
+        's,
+        (
+            Option<&'static Chain>,
+            Option<&'static mut Pierce>,
+            Option<&'static Split>,
+            Option<&'static Spawnable>,
+        ),
+    >,
+    dealt: EventWriter<'w, DamageDealt>,
+    exploded: EventWriter<'w, Exploded>,
+    recycler: Recycler<'w, 's>,
+    time: Res<'w, Time>,
+}
+
+impl Impacts<'_, '_> {
+    /// Return the projectile to the pool
+    fn remove(&mut self, projectile: Entity) {
+        if self.recycler.recycle(&mut self.commands, projectile) {
+            self.commands
+                .entity(projectile)
+                .remove::<ProjectileTarget>();
         }
-        Damage::Magical(d) => {
-            if let Ok((_, mut health)) = targets.get_mut(target) {
-                health.0 -= d;
+    }
+
+    /// Units the projectile has already passed through
+    fn already_hit(&self, projectile: Entity) -> Vec<Entity> {
+        self.modifiers
+            .get(projectile)
+            .ok()
+            .and_then(|(_, pierce, ..)| pierce.map(|p| p.hit.clone()))
+            .unwrap_or_default()
+    }
+
+    /// The first hostile unit within `radius` of the line from `from` to `to`
+    fn collide(
+        &self,
+        projectile: Entity,
+        from: Vec3,
+        to: Vec3,
+        radius: f32,
+        faction: Faction,
+    ) -> Option<Entity> {
+        let hit = self.already_hit(projectile);
+        let line = to - from;
+        let len2 = line.length_squared().max(f32::EPSILON);
+        self.targets
+            .iter()
+            .filter(|(e, .., f)| !hit.contains(e) && faction.hostile(**f))
+            .filter_map(|(e, gt, ..)| {
+                let pos = gt.translation() + Vec3::Y * 0.3;
+                let t = ((pos - from).dot(line) / len2).clamp(0.0, 1.0);
+                let closest = from + line * t;
+                (closest.distance_squared(pos) < radius * radius).then_some((e, t))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(e, _)| e)
+    }
+
+    fn deal_damage(
+        &mut self,
+        pos: Vec3,
+        damage: Damage,
+        target: Entity,
+        source: Entity,
+        faction: Faction,
+    ) {
+        /// How far units at the centre of an explosion are pushed back along their curve
+        const KNOCKBACK: f32 = 2.0;
+
+        match damage {
+            Damage::Physical(d) | Damage::Magical(d) => {
+                let Ok((entity, gt, mut health, _, f)) = self.targets.get_mut(target) else {
+                    return;
+                };
+                if faction.hostile(*f) {
+                    let amount = health.hurt(d);
+                    self.dealt.send(DamageDealt {
+                        source: Some(source),
+                        target: entity,
+                        damage,
+                        amount,
+                        lethal: amount > 0.0 && health.0 <= 0.0,
+                        position: gt.translation(),
+                    });
+                }
+            }
+            Damage::Explosive(d, r, falloff) => {
+                self.exploded.send(Exploded { position: pos });
+                Spawnable(FxLibrary::Explosion).spawn(
+                    pos,
+                    &mut self.commands,
+                    self.recycler.pool(),
+                );
+                for (entity, gt, mut health, follow, f) in self.targets.iter_mut() {
+                    let distance = pos.distance(gt.translation());
+                    if distance < r && faction.hostile(*f) {
+                        let factor = falloff.factor(distance, r);
+                        let amount = health.hurt(d * factor);
+                        self.dealt.send(DamageDealt {
+                            source: Some(source),
+                            target: entity,
+                            damage,
+                            amount,
+                            lethal: amount > 0.0 && health.0 <= 0.0,
+                            position: gt.translation(),
+                        });
+                        if let Some(mut follow) = follow {
+                            follow.nudge(-KNOCKBACK * factor, 0.0);
+                        }
+                    }
+                }
             }
         }
-        Damage::Explosive(d, r) => {
-            let r2 = r * r;
-            for (gt, mut health) in targets.iter_mut() {
-                if pos.distance_squared(gt.translation()) < r2 {
-                    health.0 -= d;
+    }
+
+    /// Damage the target and apply the modifiers of the projectile.
+    /// Returns false if the projectile continues (with a new target).
+    fn impact(
+        &mut self,
+        projectile: Entity,
+        pos: Vec3,
+        damage: Damage,
+        target: &mut ProjectileTarget,
+        dir: Vec3,
+    ) -> bool {
+        let damage = damage.scaled(target.power);
+        self.deal_damage(pos, damage, target.entity, target.source, target.faction);
+        let hit_pos = self
+            .targets
+            .get(target.entity)
+            .map_or(pos, |(_, gt, ..)| gt.translation());
+        let Ok((chain, pierce, split, spawnable)) = self.modifiers.get_mut(projectile) else {
+            self.remove(projectile);
+            return true;
+        };
+        let (chain, split, spawnable) = (chain.cloned(), split.cloned(), spawnable.cloned());
+
+        if let Some(mut pierce) = pierce {
+            pierce.hit.push(target.entity);
+            if pierce.hit.len() <= pierce.count as usize {
+                let next = units_near(
+                    &self.targets,
+                    hit_pos,
+                    pierce.range,
+                    &pierce.hit,
+                    target.faction,
+                )
+                .into_iter()
+                .find(|(_, p, _)| (*p - hit_pos).normalize_or_zero().dot(dir) > 0.7);
+                if let Some((next, next_pos, _)) = next {
+                    self.deal_chain(chain, damage, target, hit_pos);
+                    target.entity = next;
+                    target.target = next_pos;
+                    return false;
                 }
-                // TODO FX
             }
         }
+        self.deal_chain(chain, damage, target, hit_pos);
+        if let Some(split) = split {
+            let near = units_near(
+                &self.targets,
+                hit_pos,
+                split.range,
+                &[target.entity],
+                target.faction,
+            );
+            for (unit, unit_pos, _) in near.into_iter().take(split.count as usize) {
+                self.recycler
+                    .pool()
+                    .spawn(&mut self.commands, split.projectile.path())
+                    .insert((
+                        LevelLocal,
+                        ProjectileTarget::new(
+                            unit,
+                            unit_pos,
+                            pos + Vec3::Y * 0.5,
+                            target.source,
+                            target.aim,
+                            target.faction,
+                        )
+                        .with_power(target.power),
+                    ));
+            }
+        }
+        self.remove(projectile);
+        if let Some(spawnable) = spawnable {
+            spawnable.spawn(pos, &mut self.commands, self.recycler.pool());
+        }
+        true
+    }
+
+    fn deal_chain(
+        &mut self,
+        chain: Option<Chain>,
+        mut damage: Damage,
+        target: &ProjectileTarget,
+        mut from: Vec3,
+    ) {
+        let Some(chain) = chain else {
+            return;
+        };
+        let until = self.time.elapsed() + Zap::DURATION;
+        let mut hit = vec![target.entity];
+        for _ in 0..chain.jumps {
+            damage = damage.scaled(chain.decay);
+            let Some(&(next, next_pos, _)) =
+                units_near(&self.targets, from, chain.range, &hit, target.faction).first()
+            else {
+                break;
+            };
+            self.deal_damage(next_pos, damage, next, target.source, target.faction);
+            self.commands.spawn((
+                LevelLocal,
+                Zap {
+                    from,
+                    to: next_pos,
+                    color: chain.color,
+                },
+                DespawnTimer(until),
+            ));
+            hit.push(next);
+            from = next_pos;
+        }
+    }
+}
+
+fn draw_zaps(q: Query<&Zap>, mut gizmos: Gizmos) {
+    for zap in q.iter() {
+        gizmos.line(zap.from + Vec3::Y, zap.to + Vec3::Y, zap.color);
     }
 }
 
@@ -253,14 +759,24 @@ pub struct ProjectilePlugin;
 impl Plugin for ProjectilePlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<Damage>()
+            .register_type::<Falloff>()
             .register_type::<ProjectilePrefab>()
             .editor_registry::<HomingProjectile>()
             .editor_registry::<RayProjectile>()
             .editor_registry::<DumbProjectile>()
-            .add_systems(PreUpdate, (setup_projectile, setup_ray).in_set(Gameplay))
+            .editor_registry::<Chain>()
+            .editor_registry::<Pierce>()
+            .editor_registry::<Split>()
+            .editor_registry::<Collider>()
+            .add_systems(
+                PreUpdate,
+                (setup_projectile, reset_projectile, setup_ray)
+                    .chain()
+                    .in_set(Gameplay),
+            )
             .add_systems(
                 Update,
-                (shoot_dumb, shoot_homing, shoot_ray).in_set(Gameplay),
+                (shoot_dumb, shoot_homing, shoot_ray, draw_zaps).in_set(Gameplay),
             );
         #[cfg(feature = "editor")]
         app.editor_bundle(
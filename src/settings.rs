@@ -0,0 +1,30 @@
+use bevy::prelude::*;
+
+#[derive(Clone, Resource)]
+pub struct Settings {
+    pub health_bars: bool,
+    pub damage_numbers: bool,
+    pub screen_shake: bool,
+    pub minimap: bool,
+    pub pooling: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            health_bars: true,
+            damage_numbers: true,
+            screen_shake: true,
+            minimap: true,
+            pooling: true,
+        }
+    }
+}
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Settings>();
+    }
+}
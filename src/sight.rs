@@ -0,0 +1,220 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+use bevy::render::primitives::Aabb;
+use bevy::utils::HashMap;
+use space_editor::prelude::*;
+
+use crate::level::Gameplay;
+
+/// Terrain and obstacles blocking the line of sight of towers (including all child meshes)
+#[derive(Component, Reflect, Clone, Copy, Default)]
+#[reflect(Component, Default)]
+pub struct Occluder;
+
+/// Meshes belonging to an `Occluder`
+#[derive(Component, Clone, Copy)]
+struct OccluderMesh;
+
+fn mark_occluders(
+    mut commands: Commands,
+    meshes: Query<Entity, Added<Handle<Mesh>>>,
+    occluders: Query<(), With<Occluder>>,
+    parents: Query<&Parent>,
+) {
+    for entity in meshes.iter() {
+        if occluders.contains(entity)
+            || parents
+                .iter_ancestors(entity)
+                .any(|e| occluders.contains(e))
+        {
+            commands.entity(entity).insert(OccluderMesh);
+        }
+    }
+}
+
+/// Triangles of a mesh that are close together, with their bounds
+struct Chunk {
+    min: Vec3,
+    max: Vec3,
+    triangles: Vec<[Vec3; 3]>,
+}
+
+/// The triangles of each occluder mesh, split into chunks on a grid so that
+/// a line only needs to be tested against the triangles of the chunks it passes through
+#[derive(Resource, Default)]
+pub struct OccluderChunks(HashMap<AssetId<Mesh>, Vec<Chunk>>);
+
+impl OccluderChunks {
+    /// Roughly how many triangles to put in each chunk
+    const TRIANGLES_PER_CHUNK: usize = 64;
+
+    fn build(mesh: &Mesh) -> Vec<Chunk> {
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            return Vec::new();
+        };
+        let vertex = |i: usize| Vec3::from(positions[i]);
+        let triangles: Vec<[Vec3; 3]> = match mesh.indices() {
+            Some(Indices::U16(indices)) => indices
+                .chunks_exact(3)
+                .map(|t| [0, 1, 2].map(|i| vertex(t[i] as usize)))
+                .collect(),
+            Some(Indices::U32(indices)) => indices
+                .chunks_exact(3)
+                .map(|t| [0, 1, 2].map(|i| vertex(t[i] as usize)))
+                .collect(),
+            None => (0..positions.len() / 3)
+                .map(|t| [0, 1, 2].map(|i| vertex(t * 3 + i)))
+                .collect(),
+        };
+        if triangles.is_empty() {
+            return Vec::new();
+        }
+
+        // Occluders are mostly spread out horizontally, so the grid only divides x and z
+        let (min, max) = triangles
+            .iter()
+            .flatten()
+            .fold((Vec3::MAX, Vec3::MIN), |(min, max), &v| {
+                (min.min(v), max.max(v))
+            });
+        let cells = ((triangles.len() / Self::TRIANGLES_PER_CHUNK) as f32)
+            .sqrt()
+            .ceil()
+            .max(1.0) as usize;
+        let size = (max - min).max(Vec3::splat(f32::EPSILON)) / cells as f32;
+        let mut grid: Vec<Vec<[Vec3; 3]>> = (0..cells * cells).map(|_| Vec::new()).collect();
+        for triangle in triangles {
+            let center = (triangle[0] + triangle[1] + triangle[2]) / 3.0;
+            let cell = ((center - min) / size).as_uvec3();
+            let x = (cell.x as usize).min(cells - 1);
+            let z = (cell.z as usize).min(cells - 1);
+            grid[x * cells + z].push(triangle);
+        }
+        grid.into_iter()
+            .filter(|triangles| !triangles.is_empty())
+            .map(|triangles| {
+                let (min, max) = triangles
+                    .iter()
+                    .flatten()
+                    .fold((Vec3::MAX, Vec3::MIN), |(min, max), &v| {
+                        (min.min(v), max.max(v))
+                    });
+                Chunk {
+                    min,
+                    max,
+                    triangles,
+                }
+            })
+            .collect()
+    }
+}
+
+fn build_chunks(
+    mut chunks: ResMut<OccluderChunks>,
+    mut events: EventReader<AssetEvent<Mesh>>,
+    occluders: Query<&Handle<Mesh>, With<OccluderMesh>>,
+    meshes: Res<Assets<Mesh>>,
+) {
+    for ev in events.read() {
+        if let AssetEvent::Modified { id } | AssetEvent::Removed { id } = ev {
+            chunks.0.remove(id);
+        }
+    }
+    for handle in occluders.iter() {
+        if !chunks.0.contains_key(&handle.id()) {
+            if let Some(mesh) = meshes.get(handle) {
+                chunks.0.insert(handle.id(), OccluderChunks::build(mesh));
+            }
+        }
+    }
+}
+
+/// Raycasts against the meshes of all occluders
+#[allow(clippy::type_complexity)]
+#[derive(SystemParam)]
+pub struct LineOfSight<'w, 's> {
+    occluders: Query<
+        'w,
+        's,
+        (
+            &'static Handle<Mesh>,
+            &'static GlobalTransform,
+            Option<&'static Aabb>,
+        ),
+        With<OccluderMesh>,
+    >,
+    chunks: Res<'w, OccluderChunks>,
+}
+
+impl LineOfSight<'_, '_> {
+    /// Is the straight line between the points free from occluders
+    pub fn clear(&self, from: Vec3, to: Vec3) -> bool {
+        self.occluders.iter().all(|(handle, gt, aabb)| {
+            let Some(chunks) = self.chunks.0.get(&handle.id()) else {
+                return true;
+            };
+            let inverse = gt.affine().inverse();
+            let from = inverse.transform_point3(from);
+            let to = inverse.transform_point3(to);
+            if aabb.is_some_and(|aabb| {
+                !segment_hits_aabb(from, to, aabb.min().into(), aabb.max().into())
+            }) {
+                return true;
+            }
+            !chunks.iter().any(|chunk| {
+                segment_hits_aabb(from, to, chunk.min, chunk.max)
+                    && chunk
+                        .triangles
+                        .iter()
+                        .any(|&triangle| segment_hits_triangle(from, to, triangle))
+            })
+        })
+    }
+}
+
+fn segment_hits_aabb(from: Vec3, to: Vec3, min: Vec3, max: Vec3) -> bool {
+    let inv = (to - from).recip();
+    let t1 = (min - from) * inv;
+    let t2 = (max - from) * inv;
+    let near = t1.min(t2).max_element();
+    let far = t1.max(t2).min_element();
+    near <= far && far >= 0.0 && near <= 1.0
+}
+
+/// Möller–Trumbore intersection, limited to the segment between the points
+fn segment_hits_triangle(from: Vec3, to: Vec3, [a, b, c]: [Vec3; 3]) -> bool {
+    let dir = to - from;
+    let e1 = b - a;
+    let e2 = c - a;
+    let p = dir.cross(e2);
+    let det = e1.dot(p);
+    if det.abs() < f32::EPSILON {
+        return false;
+    }
+    let inv = 1.0 / det;
+    let s = from - a;
+    let u = s.dot(p) * inv;
+    if !(0.0..=1.0).contains(&u) {
+        return false;
+    }
+    let q = s.cross(e1);
+    let v = dir.dot(q) * inv;
+    if v < 0.0 || u + v > 1.0 {
+        return false;
+    }
+    let t = e2.dot(q) * inv;
+    t > 0.0 && t < 1.0
+}
+
+pub struct SightPlugin;
+
+impl Plugin for SightPlugin {
+    fn build(&self, app: &mut App) {
+        app.editor_registry::<Occluder>()
+            .init_resource::<OccluderChunks>()
+            .add_systems(Update, (mark_occluders, build_chunks).in_set(Gameplay));
+    }
+}
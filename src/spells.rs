@@ -0,0 +1,284 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy::window::PrimaryWindow;
+use bevy_egui::egui::{self, Align2};
+use bevy_egui::EguiContexts;
+use enum_iterator::{all, Sequence};
+
+use crate::events::{DamageDealt, Exploded};
+use crate::faction::Faction;
+use crate::fx::{FxLibrary, Spawnable};
+use crate::level::{GameStats, Gameplay, Level};
+use crate::pool::Pool;
+use crate::projectile::Damage;
+use crate::spline::FollowCurve;
+use crate::tower::{Terrified, Tower};
+use crate::unit::Unit;
+
+/// Spells the archdemon lends the player, paid with souls
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Sequence)]
+pub enum Spell {
+    /// Damage all towers in the area
+    Hellfire,
+    /// Speed up all units in the area
+    Haste,
+    /// Stop all towers in the area from shooting
+    Terror,
+}
+
+impl Spell {
+    const HELLFIRE_DAMAGE: f32 = 60.0;
+    const HASTE_BOOST: f32 = 1.6;
+    const HASTE_DURATION: Duration = Duration::from_secs(6);
+    const TERROR_DURATION: Duration = Duration::from_secs(5);
+
+    #[inline]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Spell::Hellfire => "Hellfire",
+            Spell::Haste => "Haste",
+            Spell::Terror => "Terror",
+        }
+    }
+
+    #[inline]
+    pub const fn cost(&self) -> u32 {
+        match self {
+            Spell::Hellfire => 4,
+            Spell::Haste => 2,
+            Spell::Terror => 3,
+        }
+    }
+
+    #[inline]
+    pub const fn cooldown(&self) -> Duration {
+        match self {
+            Spell::Hellfire => Duration::from_secs(20),
+            Spell::Haste => Duration::from_secs(10),
+            Spell::Terror => Duration::from_secs(15),
+        }
+    }
+
+    #[inline]
+    pub const fn radius(&self) -> f32 {
+        match self {
+            Spell::Hellfire => 4.0,
+            Spell::Haste => 6.0,
+            Spell::Terror => 5.0,
+        }
+    }
+
+    #[inline]
+    const fn key(&self) -> KeyCode {
+        match self {
+            Spell::Hellfire => KeyCode::Digit1,
+            Spell::Haste => KeyCode::Digit2,
+            Spell::Terror => KeyCode::Digit3,
+        }
+    }
+}
+
+/// The spell being aimed, and when each spell can be cast again
+#[derive(Resource, Default)]
+pub struct Spells {
+    pub aiming: Option<Spell>,
+    ready: HashMap<Spell, Duration>,
+}
+
+impl Spells {
+    pub fn remaining(&self, spell: Spell, time: Duration) -> Duration {
+        self.ready
+            .get(&spell)
+            .map_or(Duration::ZERO, |&r| r.saturating_sub(time))
+    }
+
+    pub fn can_cast(&self, spell: Spell, stats: &GameStats, time: Duration) -> bool {
+        stats.souls_current >= spell.cost() && self.remaining(spell, time).is_zero()
+    }
+}
+
+/// Units moving faster until the time has passed
+#[derive(Component, Clone, Copy)]
+pub struct Hasted(pub Duration);
+
+fn spell_bar(
+    mut contexts: EguiContexts,
+    mut spells: ResMut<Spells>,
+    stats: Res<GameStats>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    time: Res<Time>,
+) {
+    let time = time.elapsed();
+    let use_keys = !contexts.ctx_mut().wants_keyboard_input();
+    for spell in all::<Spell>() {
+        if use_keys && keys.just_pressed(spell.key()) {
+            spells.aiming = if spells.aiming == Some(spell) {
+                None
+            } else {
+                Some(spell)
+            };
+        }
+    }
+    if mouse.just_pressed(MouseButton::Right) {
+        spells.aiming = None;
+    }
+    egui::Area::new("spells")
+        .anchor(Align2::CENTER_BOTTOM, egui::vec2(0.0, -40.0))
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                for (i, spell) in all::<Spell>().enumerate() {
+                    let remaining = spells.remaining(spell, time);
+                    let text = if remaining.is_zero() {
+                        format!("{} [{}]\n{} souls", spell.name(), i + 1, spell.cost())
+                    } else {
+                        format!("{} [{}]\n{}s", spell.name(), i + 1, remaining.as_secs() + 1)
+                    };
+                    let button = egui::Button::new(text)
+                        .selected(spells.aiming == Some(spell))
+                        .min_size(egui::vec2(90.0, 40.0));
+                    let enabled = spells.can_cast(spell, &stats, time);
+                    if ui.add_enabled(enabled, button).clicked() {
+                        spells.aiming = Some(spell);
+                    }
+                }
+            });
+        });
+}
+
+/// Find where the cursor points at the ground
+fn ground_point(
+    windows: &Query<&Window, With<PrimaryWindow>>,
+    cameras: &Query<(&Camera, &GlobalTransform)>,
+) -> Option<Vec3> {
+    let cursor = windows.get_single().ok()?.cursor_position()?;
+    let (camera, camera_gt) = cameras.iter().find(|(c, _)| c.is_active)?;
+    let ray = camera.viewport_to_world(camera_gt, cursor)?;
+    let distance = ray.intersect_plane(Vec3::ZERO, Plane3d::new(Vec3::Y))?;
+    Some(ray.get_point(distance))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn cast_spell(
+    mut commands: Commands,
+    mut spells: ResMut<Spells>,
+    mut stats: ResMut<GameStats>,
+    mut towers: Query<(Entity, &mut Tower, &GlobalTransform, &Faction)>,
+    units: Query<(Entity, &GlobalTransform, &Faction), With<Unit>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut contexts: EguiContexts,
+    mut exploded: EventWriter<Exploded>,
+    mut dealt: EventWriter<DamageDealt>,
+    mut gizmos: Gizmos,
+    mut pool: ResMut<Pool>,
+    time: Res<Time>,
+) {
+    let Some(spell) = spells.aiming else {
+        return;
+    };
+    let Some(point) = ground_point(&windows, &cameras) else {
+        return;
+    };
+    let time = time.elapsed();
+    let castable = spells.can_cast(spell, &stats, time);
+    gizmos.circle(
+        point + Vec3::Y * 0.1,
+        Direction3d::Y,
+        spell.radius(),
+        if castable {
+            Color::ORANGE_RED
+        } else {
+            Color::GRAY
+        },
+    );
+    if !castable
+        || !mouse.just_pressed(MouseButton::Left)
+        || contexts.ctx_mut().is_pointer_over_area()
+    {
+        return;
+    }
+    let radius = spell.radius() * spell.radius();
+    match spell {
+        Spell::Hellfire => {
+            for (entity, mut tower, gt, faction) in towers.iter_mut() {
+                if Faction::PLAYER.hostile(*faction)
+                    && gt.translation().distance_squared(point) < radius
+                {
+                    let amount = tower.hurt(Spell::HELLFIRE_DAMAGE);
+                    dealt.send(DamageDealt {
+                        source: None,
+                        target: entity,
+                        damage: Damage::Magical(Spell::HELLFIRE_DAMAGE),
+                        amount,
+                        lethal: amount > 0.0 && tower.health_fraction() <= 0.0,
+                        position: gt.translation(),
+                    });
+                }
+            }
+            Spawnable(FxLibrary::Explosion).spawn(point, &mut commands, &mut pool);
+            exploded.send(Exploded { position: point });
+        }
+        Spell::Haste => {
+            for (entity, gt, faction) in units.iter() {
+                if *faction == Faction::PLAYER && gt.translation().distance_squared(point) < radius
+                {
+                    commands
+                        .get_entity(entity)
+                        .unwrap()
+                        .insert(Hasted(time + Spell::HASTE_DURATION));
+                }
+            }
+        }
+        Spell::Terror => {
+            for (entity, _, gt, faction) in towers.iter() {
+                if Faction::PLAYER.hostile(*faction)
+                    && gt.translation().distance_squared(point) < radius
+                {
+                    commands
+                        .get_entity(entity)
+                        .unwrap()
+                        .insert(Terrified(time + Spell::TERROR_DURATION));
+                }
+            }
+        }
+    }
+    stats.souls_current -= spell.cost();
+    spells.ready.insert(spell, time + spell.cooldown());
+    spells.aiming = None;
+}
+
+fn haste(
+    mut commands: Commands,
+    mut units: Query<(Entity, &Hasted, &mut FollowCurve)>,
+    time: Res<Time>,
+) {
+    let time = time.elapsed();
+    for (entity, hasted, mut follow) in units.iter_mut() {
+        if hasted.0 < time {
+            follow.set_boost(1.0);
+            commands.get_entity(entity).unwrap().remove::<Hasted>();
+        } else {
+            follow.set_boost(Spell::HASTE_BOOST);
+        }
+    }
+}
+
+pub struct SpellsPlugin;
+
+impl Plugin for SpellsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Spells>()
+            .add_systems(Update, haste.in_set(Gameplay))
+            .add_systems(
+                Update,
+                (spell_bar, cast_spell)
+                    .chain()
+                    .in_set(Gameplay)
+                    .run_if(not(in_state(Level::MainMenu))),
+            );
+    }
+}
@@ -4,33 +4,71 @@ use space_editor::prelude::*;
 
 use crate::level::Gameplay;
 
+/// A curve in the local space of its entity, evaluate it through the entity's `GlobalTransform`
 #[derive(Component, Clone)]
 pub struct Curve {
     curve: CubicCurve<Vec3>,
-    step: f32,
+    /// Arc length at evenly spaced curve parameters, for mapping distances to positions
+    lengths: Vec<f32>,
 }
 
 impl Curve {
-    #[allow(unused)]
-    pub fn position(&self, pos: f32, length: f32) -> (f32, Vec3) {
-        let start = self.curve.position(pos * self.step);
-        let l2 = length * length;
-        let mut lowp = pos;
-        let mut highp = pos + length;
-        while start.distance_squared(self.curve.position(highp * self.step)) < l2 {
-            lowp = highp;
-            highp += highp - pos;
-        }
-        let mut midp = highp * 0.5 + lowp * 0.5;
-        for _ in 0..3 {
-            if start.distance_squared(self.curve.position(midp * self.step)) < l2 {
-                lowp = midp;
-            } else {
-                highp = midp;
-            }
-            midp = highp * 0.5 + lowp * 0.5
+    const SAMPLES_PER_SEGMENT: usize = 32;
+    /// How far the end of a curve can be from the start of the next one, or from a goal
+    pub const LINK_DISTANCE: f32 = 5.0;
+
+    pub fn new(curve: CubicCurve<Vec3>) -> Self {
+        let samples = curve.segments().len() * Self::SAMPLES_PER_SEGMENT;
+        let mut lengths = Vec::with_capacity(samples + 1);
+        let mut prev = curve.position(0.0);
+        let mut length = 0.0;
+        for pos in curve.iter_positions(samples) {
+            length += prev.distance(pos);
+            lengths.push(length);
+            prev = pos;
         }
-        (midp, self.curve.position(midp * self.step))
+        Self { curve, lengths }
+    }
+
+    pub fn length(&self) -> f32 {
+        self.lengths.last().copied().unwrap_or_default()
+    }
+
+    /// The curve parameter at a distance along the curve (clamped to the ends)
+    fn parameter(&self, distance: f32) -> f32 {
+        let i = self.lengths.partition_point(|&l| l < distance);
+        let frac = if i == 0 {
+            0.0
+        } else if i >= self.lengths.len() {
+            (self.lengths.len() - 1) as f32
+        } else {
+            let (low, high) = (self.lengths[i - 1], self.lengths[i]);
+            (i - 1) as f32 + (distance - low) / (high - low).max(f32::EPSILON)
+        };
+        frac / Self::SAMPLES_PER_SEGMENT as f32
+    }
+
+    pub fn position(&self, distance: f32) -> Vec3 {
+        self.curve.position(self.parameter(distance))
+    }
+
+    pub fn tangent(&self, distance: f32) -> Vec3 {
+        self.curve
+            .velocity(self.parameter(distance))
+            .normalize_or_zero()
+    }
+
+    pub fn start(&self) -> Vec3 {
+        self.position(0.0)
+    }
+
+    pub fn end(&self) -> Vec3 {
+        self.position(self.length())
+    }
+
+    /// Position at `t` in `[0, 1]` along the whole curve
+    pub fn sample(&self, t: f32) -> Vec3 {
+        self.position(t * self.length())
     }
 }
 
@@ -38,7 +76,7 @@ impl Default for Curve {
     fn default() -> Self {
         Self {
             curve: CubicBSpline::new([]).to_curve(),
-            step: 0.0,
+            lengths: vec![0.0],
         }
     }
 }
@@ -51,51 +89,59 @@ pub struct Spline;
 #[reflect(Component, Default)]
 pub struct Width(pub f32);
 
+/// What happens to units that reach the end of a curve
+#[derive(Component, Clone, Copy, Reflect, Default, PartialEq, Debug)]
+#[reflect(Component, Default)]
+pub enum PathEnd {
+    Despawn,
+    /// Count as having reached the goal
+    #[default]
+    Score,
+    /// Continue on the curve that starts closest to the end of this one
+    Continue,
+    Idle,
+}
+
 /// Splines into Curves
 fn convert_splines(
     mut commands: Commands,
     splines: Query<(Entity, &Children), With<Spline>>,
-    nodes: Query<&GlobalTransform, With<Parent>>,
+    nodes: Query<&Transform, With<Parent>>,
 ) {
     for (entity, children) in &splines {
         if let Some(curve) = get_curve(children, &nodes) {
-            let divs = curve.segments().len();
-            let start = curve.position(0.0);
-            let length = curve
-                .iter_positions(divs * 20)
-                .fold((0.0, start), |(len, prev), next| {
-                    (len + prev.distance(next), next)
-                })
-                .0;
             commands
                 .entity(entity)
                 .despawn_descendants()
                 .remove::<Spline>()
-                .insert(Curve {
-                    curve,
-                    step: divs as f32 / length,
-                });
+                .insert(Curve::new(curve));
         }
     }
 }
 
 #[allow(unused)]
 fn debug_gizmos(
-    splines: Query<(&Spline, &Children)>,
-    nodes: Query<&GlobalTransform, With<Parent>>,
+    splines: Query<(&GlobalTransform, &Children), With<Spline>>,
+    nodes: Query<&Transform, With<Parent>>,
     mut gizmos: Gizmos,
 ) {
-    for (spline, children) in &splines {
+    for (gt, children) in &splines {
         if let Some(curve) = get_curve(children, &nodes) {
             let divisions = curve.segments().len() * 10;
-            gizmos.linestrip(curve.iter_positions(divisions), Color::WHITE);
+            gizmos.linestrip(
+                curve
+                    .iter_positions(divisions)
+                    .map(|p| gt.transform_point(p)),
+                Color::WHITE,
+            );
         }
     }
 }
 
+/// Build a curve from the control points, in the local space of the spline
 fn get_curve(
     children: &Children,
-    nodes: &Query<&GlobalTransform, With<Parent>>,
+    nodes: &Query<&Transform, With<Parent>>,
 ) -> Option<CubicCurve<Vec3>> {
     if children.is_empty() {
         return None;
@@ -103,9 +149,8 @@ fn get_curve(
     let mut points = Vec::with_capacity(children.len() + 2);
     points.push(Vec3::ZERO);
     for &child in children.iter() {
-        if let Ok(gt) = nodes.get(child) {
-            // TODO transform curve into local space
-            points.push(gt.translation());
+        if let Ok(trans) = nodes.get(child) {
+            points.push(trans.translation);
         }
     }
     if points.len() > 2 {
@@ -121,37 +166,130 @@ fn get_curve(
 pub struct FollowCurve {
     curve: Entity,
     speed: f32,
+    /// Multiplier for the speed, from spells and effects
+    boost: f32,
+    /// Multiplier for the speed, from slowing towers
+    slow: f32,
     along: f32,
-    offset: Vec3,
+    /// Sideways offset from the curve, within `[-width, width]`
+    lateral: f32,
+    width: f32,
+    /// Distance travelled on previous curves
+    previous: f32,
+    finished: bool,
 }
 
 impl FollowCurve {
-    pub fn new(curve: Entity, speed: f32, offset: Vec3) -> Self {
+    pub fn new(curve: Entity, speed: f32, lateral: f32, width: f32) -> Self {
         FollowCurve {
             curve,
             speed,
+            boost: 1.0,
+            slow: 1.0,
             along: 0.0,
-            offset,
+            lateral: lateral.clamp(-width, width),
+            width,
+            previous: 0.0,
+            finished: false,
         }
     }
 
+    /// Total distance travelled
     pub fn distance(&self) -> f32 {
+        self.previous + self.along
+    }
+
+    pub fn curve(&self) -> Entity {
+        self.curve
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    pub fn set_boost(&mut self, boost: f32) {
+        self.boost = boost;
+    }
+
+    pub fn slow(&self) -> f32 {
+        self.slow
+    }
+
+    pub fn set_slow(&mut self, slow: f32) {
+        self.slow = slow;
+    }
+
+    /// Current speed including all multipliers
+    fn velocity(&self) -> f32 {
+        self.speed * self.boost * self.slow
+    }
+
+    /// Distance along the current curve
+    pub fn along(&self) -> f32 {
         self.along
     }
+
+    pub fn lateral(&self) -> f32 {
+        self.lateral
+    }
+
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    /// Move within the lane, forwards/backwards and sideways (limited by the width)
+    pub fn nudge(&mut self, along: f32, lateral: f32) {
+        self.along = (self.along + along).max(0.0);
+        self.lateral = (self.lateral + lateral).clamp(-self.width, self.width);
+    }
+
+    /// Has the end of the curve been reached
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Continue from the start of another curve
+    pub fn switch(&mut self, curve: Entity) {
+        self.previous += self.along;
+        self.along = 0.0;
+        self.curve = curve;
+        self.finished = false;
+    }
+
+    /// World position and direction at a distance along the curve
+    fn place(&self, curve: &Curve, gt: &GlobalTransform, along: f32) -> (Vec3, Vec3) {
+        let forward = gt.affine().transform_vector3(curve.tangent(along));
+        let side = forward.cross(Vec3::Y).normalize_or_zero();
+        let pos = gt.transform_point(curve.position(along)) + side * self.lateral;
+        (pos, forward)
+    }
+
+    /// Where the follower will be in `seconds` if it keeps its current speed
+    pub fn predict(&self, curve: &Curve, gt: &GlobalTransform, seconds: f32) -> Vec3 {
+        let along = (self.along + self.velocity() * seconds).min(curve.length());
+        self.place(curve, gt, along).0
+    }
 }
 
 pub fn follow_curve(
     mut query: Query<(&mut FollowCurve, &mut Transform)>,
-    curves: Query<(Entity, &Curve)>,
+    curves: Query<(&Curve, &GlobalTransform)>,
     time: Res<Time>,
 ) {
     for (mut follow, mut trans) in query.iter_mut() {
-        if let Ok((_, curve)) = curves.get(follow.curve) {
-            let (pos, mut vec) = curve.position(follow.along, follow.speed * time.delta_seconds());
-            vec += follow.offset;
-            follow.along = pos;
-            trans.look_at(vec, Vec3::Y);
-            trans.translation = vec;
+        if let Ok((curve, gt)) = curves.get(follow.curve) {
+            follow.along =
+                (follow.along + follow.velocity() * time.delta_seconds()).min(curve.length());
+            follow.finished = follow.along >= curve.length();
+            let (pos, forward) = follow.place(curve, gt, follow.along);
+            trans.translation = pos;
+            if forward != Vec3::ZERO {
+                trans.look_to(forward, Vec3::Y);
+            }
         }
     }
 }
@@ -163,6 +301,7 @@ impl Plugin for SplinePlugin {
         app.add_systems(PreUpdate, (convert_splines).in_set(Gameplay))
             .add_systems(Update, (follow_curve).in_set(Gameplay))
             .editor_registry::<Width>()
+            .editor_registry::<PathEnd>()
             .editor_registry::<Spline>();
         #[cfg(feature = "editor")]
         app.add_systems(Update, debug_gizmos.run_if(in_state(EditorState::Editor)))
@@ -173,6 +312,7 @@ impl Plugin for SplinePlugin {
                     TransformBundle::default(),
                     Spline,
                     Width(2.0),
+                    PathEnd::default(),
                     Name::new("Curve"),
                 ),
             );
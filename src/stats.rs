@@ -0,0 +1,180 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use crate::events::{
+    DamageDealt, ProjectileFired, UnitDied, UnitReachedGoal, UnitSpawned, Upgrade, UpgradePurchased,
+};
+use crate::level::{GameStats, Gameplay, Level};
+use crate::tower::Tower;
+use crate::unit::UnitPrefab;
+
+/// Statistics of a single tower, kept after it is destroyed
+#[derive(Clone, Copy)]
+pub struct TowerRecord {
+    pub entity: Entity,
+    pub damage_taken: f32,
+    pub shots: u32,
+}
+
+/// Statistics for the end-of-level summary
+#[derive(Clone, Resource, Default)]
+pub struct Statistics {
+    pub summoned: HashMap<UnitPrefab, u32>,
+    pub lost: HashMap<UnitPrefab, u32>,
+    pub breached: HashMap<UnitPrefab, u32>,
+    /// When each of the living units was summoned
+    summon_times: HashMap<Entity, Duration>,
+    /// Total time survived, and by how many units, for each type of demon
+    lifetimes: HashMap<UnitPrefab, (Duration, u32)>,
+    pub towers: Vec<TowerRecord>,
+    pub upgrades: HashMap<Upgrade, u32>,
+    pub upgrade_souls: u32,
+    pub end_time: Option<Duration>,
+}
+
+impl Statistics {
+    pub fn record_summon(&mut self, entity: Entity, prefab: UnitPrefab, time: Duration) {
+        *self.summoned.entry(prefab).or_default() += 1;
+        self.summon_times.insert(entity, time);
+    }
+
+    pub fn record_loss(&mut self, entity: Entity, prefab: UnitPrefab, time: Duration) {
+        *self.lost.entry(prefab).or_default() += 1;
+        self.record_lifetime(entity, prefab, time);
+    }
+
+    pub fn record_breach(&mut self, entity: Entity, prefab: UnitPrefab, time: Duration) {
+        *self.breached.entry(prefab).or_default() += 1;
+        self.record_lifetime(entity, prefab, time);
+    }
+
+    fn record_lifetime(&mut self, entity: Entity, prefab: UnitPrefab, time: Duration) {
+        if let Some(start) = self.summon_times.remove(&entity) {
+            let (total, count) = self.lifetimes.entry(prefab).or_default();
+            *total += time.saturating_sub(start);
+            *count += 1;
+        }
+    }
+
+    /// How long the demons of the type survived before dying or breaking through
+    pub fn average_lifetime(&self, prefab: UnitPrefab) -> Option<Duration> {
+        self.lifetimes
+            .get(&prefab)
+            .filter(|(_, count)| *count > 0)
+            .map(|(total, count)| *total / *count)
+    }
+
+    fn tower(&mut self, tower: Entity) -> Option<&mut TowerRecord> {
+        self.towers.iter_mut().find(|t| t.entity == tower)
+    }
+
+    pub fn record_tower(&mut self, tower: Entity) {
+        if self.tower(tower).is_none() {
+            self.towers.push(TowerRecord {
+                entity: tower,
+                damage_taken: 0.0,
+                shots: 0,
+            });
+        }
+    }
+
+    /// Damage to anything other than a tower is ignored
+    pub fn record_damage(&mut self, target: Entity, damage: f32) {
+        if let Some(record) = self.tower(target) {
+            record.damage_taken += damage;
+        }
+    }
+
+    pub fn record_shot(&mut self, tower: Entity) {
+        if let Some(record) = self.tower(tower) {
+            record.shots += 1;
+        }
+    }
+
+    pub fn record_upgrade(&mut self, upgrade: Upgrade, cost: u32) {
+        *self.upgrades.entry(upgrade).or_default() += 1;
+        self.upgrade_souls += cost;
+    }
+
+    pub fn time_taken(&self, stats: &GameStats, time: Duration) -> Duration {
+        self.end_time
+            .unwrap_or(time)
+            .saturating_sub(stats.start_time)
+    }
+
+    /// Zero stars for a defeat, otherwise based on how quickly the defences fell
+    pub fn stars(&self, stats: &GameStats, time: Duration) -> u8 {
+        if !stats.victory() {
+            return 0;
+        }
+        let taken = self.time_taken(stats, time);
+        if taken <= GameStats::TIME_LIMIT / 2 {
+            3
+        } else if taken <= GameStats::TIME_LIMIT {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn collect(
+    mut statistics: ResMut<Statistics>,
+    mut spawned: EventReader<UnitSpawned>,
+    mut died: EventReader<UnitDied>,
+    mut reached: EventReader<UnitReachedGoal>,
+    mut dealt: EventReader<DamageDealt>,
+    mut fired: EventReader<ProjectileFired>,
+    mut upgrades: EventReader<UpgradePurchased>,
+    towers: Query<Entity, Added<Tower>>,
+    time: Res<Time>,
+) {
+    let time = time.elapsed();
+    for tower in towers.iter() {
+        statistics.record_tower(tower);
+    }
+    for ev in spawned.read() {
+        statistics.record_summon(ev.entity, ev.prefab, time);
+    }
+    for ev in died.read() {
+        if let Some(prefab) = ev.prefab {
+            statistics.record_loss(ev.entity, prefab, time);
+        }
+    }
+    for ev in reached.read() {
+        if let Some(prefab) = ev.prefab {
+            statistics.record_breach(ev.entity, prefab, time);
+        }
+    }
+    for ev in dealt.read() {
+        statistics.record_damage(ev.target, ev.amount);
+    }
+    for ev in fired.read() {
+        statistics.record_shot(ev.tower);
+    }
+    for ev in upgrades.read() {
+        statistics.record_upgrade(ev.upgrade, ev.cost);
+    }
+}
+
+fn record_end(mut statistics: ResMut<Statistics>, stats: Res<GameStats>, time: Res<Time>) {
+    if statistics.end_time.is_none() && (stats.victory() || stats.defeat(time.elapsed())) {
+        statistics.end_time = Some(time.elapsed());
+    }
+}
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Statistics>().add_systems(
+            Update,
+            (collect, record_end)
+                .in_set(Gameplay)
+                .run_if(not(in_state(Level::MainMenu))),
+        );
+    }
+}
@@ -3,23 +3,77 @@ use std::time::Duration;
 use bevy::prelude::*;
 use space_editor::prelude::*;
 
+use crate::events::{DamageDealt, ProjectileFired};
+use crate::faction::Faction;
+use crate::fx::{FxLibrary, Spawnable};
 use crate::level::{Gameplay, LevelLocal};
-use crate::projectile::{ProjectilePrefab, ProjectileTarget};
+use crate::pool::Pool;
+use crate::projectile::{Damage, ProjectilePrefab, ProjectileTarget};
+use crate::sight::LineOfSight;
 use crate::spline::FollowCurve;
-use crate::unit::Unit;
+use crate::unit::{Health, Stealth, Unit};
 
 #[derive(Component, Reflect, Clone)]
 #[reflect(Component, Default)]
 pub struct Targetter {
     range: f32,
+    /// Only target units that are not hidden behind an `Occluder`
+    line_of_sight: bool,
+    /// Can target units with `Stealth` without them being revealed
+    detection: bool,
 }
 
 impl Default for Targetter {
+    fn default() -> Self {
+        Self {
+            range: 10.0,
+            line_of_sight: false,
+            detection: false,
+        }
+    }
+}
+
+impl Targetter {
+    /// The range including buffs from support towers and levels
+    pub fn range(&self, supported: Option<&Supported>, veteran: Option<&Veteran>) -> f32 {
+        self.range * supported.map_or(1.0, |s| s.range) * veteran.map_or(1.0, Veteran::range)
+    }
+
+    /// Can a unit at the position be seen from the eye (if line of sight is required)
+    pub fn sees(&self, sight: &LineOfSight, eye: Vec3, unit: Vec3) -> bool {
+        !self.line_of_sight || sight.clear(eye, unit + Vec3::Y * 0.5)
+    }
+
+    /// Can a unit be targeted, taking stealth into account
+    pub fn detects(&self, stealth: bool, revealed: bool) -> bool {
+        !stealth || revealed || self.detection
+    }
+}
+
+/// Towers revealing units with `Stealth` in range for all towers
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component, Default)]
+pub struct Revealer {
+    range: f32,
+}
+
+impl Default for Revealer {
     fn default() -> Self {
         Self { range: 10.0 }
     }
 }
 
+impl Revealer {
+    /// The range including the buff from levels
+    pub fn range(&self, veteran: Option<&Veteran>) -> f32 {
+        self.range * veteran.map_or(1.0, Veteran::range)
+    }
+}
+
+/// Units with `Stealth` that are in range of a `Revealer`
+#[derive(Component, Clone, Copy)]
+pub struct Revealed;
+
 #[derive(Component, Reflect, Copy, Clone, Default)]
 #[reflect(Component, Default)]
 pub struct LookAtTarget;
@@ -27,14 +81,25 @@ pub struct LookAtTarget;
 #[derive(Component, Clone, Copy)]
 pub struct Target(pub Entity);
 
+/// Towers that are too scared to shoot until the time has passed
+#[derive(Component, Clone, Copy)]
+pub struct Terrified(pub Duration);
+
+/// Health and experience for all towers. Only towers that also have a `Targetter` shoot
+/// their projectile; the aura towers leave it out and use `Tower` for the rest
 #[derive(Component, Reflect, Clone)]
 #[reflect(Component, Default)]
 pub struct Tower {
     projectile: ProjectilePrefab,
     cooldown: Duration,
     height: f32,
+    health: f32,
+    /// How well the tower leads moving targets, from 0 (not at all) to 1 (perfectly)
+    aim: f32,
     #[reflect(ignore)]
     next: Duration,
+    #[reflect(ignore)]
+    damage: f32,
 }
 
 impl Default for Tower {
@@ -43,22 +108,207 @@ impl Default for Tower {
             cooldown: Duration::from_secs(1),
             height: 1.0,
             projectile: ProjectilePrefab::Arrow,
+            health: 100.0,
+            aim: 1.0,
             next: Duration::ZERO,
+            damage: 0.0,
         }
     }
 }
 
+/// Towers slowing down all units in range
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component, Default)]
+pub struct FrostAura {
+    range: f32,
+    /// Speed multiplier for units in range
+    slow: f32,
+}
+
+impl Default for FrostAura {
+    fn default() -> Self {
+        Self {
+            range: 8.0,
+            slow: 0.5,
+        }
+    }
+}
+
+impl FrostAura {
+    /// The range including the buff from levels
+    pub fn range(&self, veteran: Option<&Veteran>) -> f32 {
+        self.range * veteran.map_or(1.0, Veteran::range)
+    }
+}
+
+/// Towers continuously damaging all units in range
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component, Default)]
+pub struct HolyAura {
+    range: f32,
+    /// Damage per second
+    damage: f32,
+    /// How often the damage is dealt
+    interval: Duration,
+    #[reflect(ignore)]
+    next: Duration,
+}
+
+impl Default for HolyAura {
+    fn default() -> Self {
+        Self {
+            range: 6.0,
+            damage: 8.0,
+            interval: Duration::from_millis(500),
+            next: Duration::ZERO,
+        }
+    }
+}
+
+impl HolyAura {
+    /// The range including the buff from levels
+    pub fn range(&self, veteran: Option<&Veteran>) -> f32 {
+        self.range * veteran.map_or(1.0, Veteran::range)
+    }
+}
+
+/// Towers improving the other towers in range
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component, Default)]
+pub struct Support {
+    range: f32,
+    /// Cooldown multiplier for towers in range
+    cooldown: f32,
+    /// Range multiplier for towers in range
+    reach: f32,
+}
+
+impl Default for Support {
+    fn default() -> Self {
+        Self {
+            range: 10.0,
+            cooldown: 0.7,
+            reach: 1.2,
+        }
+    }
+}
+
+impl Support {
+    /// The range including the buff from levels
+    pub fn range(&self, veteran: Option<&Veteran>) -> f32 {
+        self.range * veteran.map_or(1.0, Veteran::range)
+    }
+}
+
+/// The best buffs from the support towers in range, only given to towers that shoot
+#[derive(Component, Clone, Copy, PartialEq)]
+pub struct Supported {
+    cooldown: f32,
+    range: f32,
+}
+
+/// Towers gain experience over time and from kills, levelling up
+#[derive(Component, Clone, Copy, Default)]
+pub struct Veteran {
+    level: u32,
+    experience: f32,
+}
+
+impl Veteran {
+    pub const MAX_LEVEL: u32 = 5;
+    const PER_SECOND: f32 = 1.0;
+    const PER_KILL: f32 = 5.0;
+
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    /// Experience needed for the next level
+    fn needed(&self) -> f32 {
+        30.0 * (self.level + 1) as f32
+    }
+
+    fn gain(&mut self, experience: f32) {
+        self.experience += experience;
+        while self.level < Self::MAX_LEVEL && self.experience >= self.needed() {
+            self.experience -= self.needed();
+            self.level += 1;
+        }
+    }
+
+    /// Range multiplier
+    pub fn range(&self) -> f32 {
+        1.0 + 0.1 * self.level as f32
+    }
+
+    /// Cooldown multiplier
+    pub fn cooldown(&self) -> f32 {
+        0.9f32.powi(self.level as i32)
+    }
+
+    /// Damage multiplier
+    pub fn power(&self) -> f32 {
+        1.0 + 0.2 * self.level as f32
+    }
+}
+
+impl Tower {
+    /// Damage the tower and return how much health was actually lost
+    pub fn hurt(&mut self, damage: f32) -> f32 {
+        let lost = damage.min((self.health - self.damage).max(0.0));
+        self.damage += damage;
+        lost
+    }
+
+    /// Remaining health as a fraction of the full health
+    pub fn health_fraction(&self) -> f32 {
+        (1.0 - self.damage / self.health).clamp(0.0, 1.0)
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+}
+
+#[allow(clippy::type_complexity)]
 fn find_target(
     mut commands: Commands,
-    q: Query<(Entity, &Targetter, &GlobalTransform), Without<Target>>,
-    units: Query<(Entity, &FollowCurve, &GlobalTransform), With<Unit>>,
+    q: Query<
+        (
+            Entity,
+            &Targetter,
+            &GlobalTransform,
+            &Faction,
+            Option<&Tower>,
+            Option<&Supported>,
+            Option<&Veteran>,
+        ),
+        (Without<Target>, Without<Terrified>),
+    >,
+    units: Query<
+        (
+            Entity,
+            &FollowCurve,
+            &GlobalTransform,
+            &Faction,
+            Has<Stealth>,
+            Has<Revealed>,
+        ),
+        With<Unit>,
+    >,
+    sight: LineOfSight,
 ) {
-    for (entity, targetter, gt) in q.iter() {
+    for (entity, targetter, gt, faction, tower, supported, veteran) in q.iter() {
         let pos = gt.translation();
+        let eye = pos + gt.up() * tower.map_or(1.0, Tower::height);
+        let range = targetter.range(supported, veteran);
         let mut furthest = f32::MIN;
-        for (target, fc, gt) in units.iter() {
-            if pos.distance_squared(gt.translation()) < targetter.range * targetter.range
+        for (target, fc, gt, unit_faction, stealth, revealed) in units.iter() {
+            if faction.hostile(*unit_faction)
+                && pos.distance_squared(gt.translation()) < range * range
                 && fc.distance() > furthest
+                && targetter.detects(stealth, revealed)
+                && targetter.sees(&sight, eye, gt.translation())
             {
                 commands.get_entity(entity).unwrap().insert(Target(target));
                 furthest = fc.distance();
@@ -79,30 +329,54 @@ fn look_at_target(
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn shoot(
     mut commands: Commands,
-    mut q: Query<(Entity, &mut Tower, &Targetter, &Target, &GlobalTransform)>,
-    units: Query<(Entity, &GlobalTransform), With<Unit>>,
+    mut q: Query<
+        (
+            Entity,
+            &mut Tower,
+            &Targetter,
+            &Target,
+            &GlobalTransform,
+            &Faction,
+            Option<&Supported>,
+            Option<&Veteran>,
+        ),
+        Without<Terrified>,
+    >,
+    units: Query<(Entity, &GlobalTransform, Has<Stealth>, Has<Revealed>), With<Unit>>,
     time: Res<Time>,
+    mut fired: EventWriter<ProjectileFired>,
+    mut pool: ResMut<Pool>,
+    sight: LineOfSight,
 ) {
     let time = time.elapsed();
-    for (entity, mut tower, targetter, target, gt) in q.iter_mut() {
+    for (entity, mut tower, targetter, target, gt, faction, supported, veteran) in q.iter_mut() {
         if tower.next < time {
-            if let Ok((unit, gt2)) = units.get(target.0) {
-                if gt.translation().distance_squared(gt2.translation())
-                    < targetter.range * targetter.range
+            if let Ok((unit, gt2, stealth, revealed)) = units.get(target.0) {
+                let range = targetter.range(supported, veteran);
+                let eye = gt.translation() + gt.up() * tower.height;
+                if gt.translation().distance_squared(gt2.translation()) < range * range
+                    && targetter.detects(stealth, revealed)
+                    && targetter.sees(&sight, eye, gt2.translation())
                 {
-                    tower.next = time + tower.cooldown;
-                    commands
-                        .spawn(PrefabBundle::new(tower.projectile.path()))
-                        .insert((
-                            LevelLocal,
-                            ProjectileTarget::new(
-                                unit,
-                                gt2.translation(),
-                                gt.translation() + gt.up() * tower.height,
-                            ),
-                        ));
+                    let cooldown = supported.map_or(1.0, |s| s.cooldown)
+                        * veteran.map_or(1.0, Veteran::cooldown);
+                    tower.next = time + tower.cooldown.mul_f32(cooldown);
+                    pool.spawn(&mut commands, tower.projectile.path()).insert((
+                        LevelLocal,
+                        ProjectileTarget::new(
+                            unit,
+                            gt2.translation(),
+                            eye,
+                            entity,
+                            tower.aim,
+                            *faction,
+                        )
+                        .with_power(veteran.map_or(1.0, Veteran::power)),
+                    ));
+                    fired.send(ProjectileFired { tower: entity });
                 } else {
                     commands.get_entity(entity).unwrap().remove::<Target>();
                 }
@@ -113,6 +387,203 @@ fn shoot(
     }
 }
 
+#[allow(clippy::type_complexity)]
+fn reveal(
+    mut commands: Commands,
+    revealers: Query<(&Revealer, &GlobalTransform, &Faction, Option<&Veteran>), Without<Terrified>>,
+    units: Query<(Entity, &GlobalTransform, &Faction, Has<Revealed>), (With<Unit>, With<Stealth>)>,
+) {
+    for (entity, gt, unit_faction, revealed) in units.iter() {
+        let pos = gt.translation();
+        let visible = revealers.iter().any(|(revealer, gt, faction, veteran)| {
+            let range = revealer.range(veteran);
+            faction.hostile(*unit_faction) && gt.translation().distance_squared(pos) < range * range
+        });
+        if visible && !revealed {
+            commands.entity(entity).insert(Revealed);
+        } else if !visible && revealed {
+            commands.entity(entity).remove::<Revealed>();
+        }
+    }
+}
+
+fn frost(
+    towers: Query<(&FrostAura, &GlobalTransform, &Faction, Option<&Veteran>), Without<Terrified>>,
+    mut units: Query<(&mut FollowCurve, &GlobalTransform, &Faction), With<Unit>>,
+) {
+    for (mut follow, gt, unit_faction) in units.iter_mut() {
+        let pos = gt.translation();
+        let slow = towers
+            .iter()
+            .filter(|(aura, gt, faction, veteran)| {
+                let range = aura.range(*veteran);
+                faction.hostile(*unit_faction)
+                    && gt.translation().distance_squared(pos) < range * range
+            })
+            .map(|(aura, ..)| aura.slow)
+            .fold(1.0, f32::min);
+        if follow.slow() != slow {
+            follow.set_slow(slow);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn holy(
+    mut towers: Query<
+        (
+            Entity,
+            &mut HolyAura,
+            &GlobalTransform,
+            &Faction,
+            Option<&Veteran>,
+        ),
+        Without<Terrified>,
+    >,
+    mut units: Query<(Entity, &GlobalTransform, &mut Health, &Faction), With<Unit>>,
+    mut dealt: EventWriter<DamageDealt>,
+    time: Res<Time>,
+) {
+    let time = time.elapsed();
+    for (entity, mut aura, gt, faction, veteran) in towers.iter_mut() {
+        if aura.next > time {
+            continue;
+        }
+        aura.next = time + aura.interval;
+        let pos = gt.translation();
+        let range = aura.range(veteran);
+        let damage =
+            aura.damage * aura.interval.as_secs_f32() * veteran.map_or(1.0, Veteran::power);
+        for (unit, gt, mut health, unit_faction) in units.iter_mut() {
+            if faction.hostile(*unit_faction)
+                && gt.translation().distance_squared(pos) < range * range
+            {
+                let amount = health.hurt(damage);
+                dealt.send(DamageDealt {
+                    source: Some(entity),
+                    target: unit,
+                    damage: Damage::Magical(damage),
+                    amount,
+                    lethal: amount > 0.0 && health.0 <= 0.0,
+                    position: gt.translation(),
+                });
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn support(
+    mut commands: Commands,
+    supports: Query<
+        (
+            Entity,
+            &Support,
+            &GlobalTransform,
+            &Faction,
+            Option<&Veteran>,
+        ),
+        Without<Terrified>,
+    >,
+    towers: Query<(Entity, &GlobalTransform, &Faction, Option<&Supported>), With<Targetter>>,
+) {
+    for (entity, gt, tower_faction, supported) in towers.iter() {
+        let pos = gt.translation();
+        let buff = supports
+            .iter()
+            .filter(|(e, support, gt, faction, veteran)| {
+                let range = support.range(*veteran);
+                *e != entity
+                    && *faction == tower_faction
+                    && gt.translation().distance_squared(pos) < range * range
+            })
+            .fold(None, |buff: Option<Supported>, (_, support, ..)| {
+                Some(Supported {
+                    cooldown: buff.map_or(support.cooldown, |b| b.cooldown.min(support.cooldown)),
+                    range: buff.map_or(support.reach, |b| b.range.max(support.reach)),
+                })
+            });
+        if buff.as_ref() != supported {
+            match buff {
+                Some(buff) => commands.entity(entity).insert(buff),
+                None => commands.entity(entity).remove::<Supported>(),
+            };
+        }
+    }
+}
+
+/// Show the area of effect of the aura and revealing towers
+fn draw_auras(
+    frost: Query<(&FrostAura, &GlobalTransform, Option<&Veteran>)>,
+    holy: Query<(&HolyAura, &GlobalTransform, Option<&Veteran>)>,
+    support: Query<(&Support, &GlobalTransform, Option<&Veteran>)>,
+    revealers: Query<(&Revealer, &GlobalTransform, Option<&Veteran>)>,
+    mut gizmos: Gizmos,
+) {
+    let mut circle = |gt: &GlobalTransform, range: f32, color| {
+        gizmos.circle(
+            gt.translation() + Vec3::Y * 0.1,
+            Direction3d::Y,
+            range,
+            color,
+        );
+    };
+    for (aura, gt, veteran) in frost.iter() {
+        circle(gt, aura.range(veteran), Color::rgb(0.5, 0.8, 1.0));
+    }
+    for (aura, gt, veteran) in holy.iter() {
+        circle(gt, aura.range(veteran), Color::GOLD);
+    }
+    for (support, gt, veteran) in support.iter() {
+        circle(gt, support.range(veteran), Color::LIME_GREEN);
+    }
+    for (revealer, gt, veteran) in revealers.iter() {
+        circle(gt, revealer.range(veteran), Color::WHITE);
+    }
+}
+
+fn enlist(mut commands: Commands, q: Query<Entity, (With<Tower>, Without<Veteran>)>) {
+    for entity in q.iter() {
+        commands.entity(entity).insert(Veteran::default());
+    }
+}
+
+/// Gain experience from the passing time and from killing units
+fn train(mut towers: Query<&mut Veteran>, mut dealt: EventReader<DamageDealt>, time: Res<Time>) {
+    for ev in dealt.read() {
+        if ev.lethal {
+            if let Some(Ok(mut veteran)) = ev.source.map(|s| towers.get_mut(s)) {
+                veteran.gain(Veteran::PER_KILL);
+            }
+        }
+    }
+    for mut veteran in towers.iter_mut() {
+        veteran.gain(Veteran::PER_SECOND * time.delta_seconds());
+    }
+}
+
+fn recover(mut commands: Commands, q: Query<(Entity, &Terrified)>, time: Res<Time>) {
+    let time = time.elapsed();
+    for (entity, terrified) in q.iter() {
+        if terrified.0 < time {
+            commands.get_entity(entity).unwrap().remove::<Terrified>();
+        }
+    }
+}
+
+fn destroy(
+    mut commands: Commands,
+    q: Query<(Entity, &Tower, &GlobalTransform), Changed<Tower>>,
+    mut pool: ResMut<Pool>,
+) {
+    for (entity, tower, gt) in q.iter() {
+        if tower.damage >= tower.health {
+            commands.get_entity(entity).unwrap().despawn_recursive();
+            Spawnable(FxLibrary::Explosion).spawn(gt.translation(), &mut commands, &mut pool);
+        }
+    }
+}
+
 #[allow(unused)]
 fn debug_gizmos(q: Query<(&Targetter, &Tower, &GlobalTransform)>, mut gizmos: Gizmos) {
     for (targetter, tower, gt) in q.iter() {
@@ -137,9 +608,27 @@ impl Plugin for TowerPlugin {
         app.editor_registry::<Targetter>()
             .editor_registry::<LookAtTarget>()
             .editor_registry::<Tower>()
+            .editor_registry::<FrostAura>()
+            .editor_registry::<HolyAura>()
+            .editor_registry::<Support>()
+            .editor_registry::<Revealer>()
             .add_systems(
                 Update,
-                (find_target, look_at_target, shoot).in_set(Gameplay),
+                (
+                    find_target,
+                    look_at_target,
+                    shoot,
+                    reveal,
+                    frost,
+                    holy,
+                    support,
+                    draw_auras,
+                    enlist,
+                    train,
+                    recover,
+                    destroy,
+                )
+                    .in_set(Gameplay),
             );
         #[cfg(feature = "editor")]
         app.add_systems(Update, debug_gizmos.run_if(in_state(EditorState::Editor)))
@@ -152,6 +641,48 @@ impl Plugin for TowerPlugin {
                     Tower::default(),
                     Name::new("Tower"),
                 ),
+            )
+            // The aura towers have no `Targetter`, so they never shoot
+            .editor_bundle(
+                "Prefab",
+                "Frost Tower",
+                (
+                    SpatialBundle::default(),
+                    Tower::default(),
+                    FrostAura::default(),
+                    Name::new("Frost Tower"),
+                ),
+            )
+            .editor_bundle(
+                "Prefab",
+                "Holy Tower",
+                (
+                    SpatialBundle::default(),
+                    Tower::default(),
+                    HolyAura::default(),
+                    Name::new("Holy Tower"),
+                ),
+            )
+            .editor_bundle(
+                "Prefab",
+                "Support Tower",
+                (
+                    SpatialBundle::default(),
+                    Tower::default(),
+                    Support::default(),
+                    Name::new("Support Tower"),
+                ),
+            )
+            .editor_bundle(
+                "Prefab",
+                "Watchtower",
+                (
+                    SpatialBundle::default(),
+                    Targetter::default(),
+                    Tower::default(),
+                    Revealer::default(),
+                    Name::new("Watchtower"),
+                ),
             );
     }
 }
@@ -1,10 +1,16 @@
 use bevy::prelude::*;
+use bevy::utils::HashMap;
 use bevy_egui::egui::{Align2, Color32, Frame, Layout, RichText, Rounding, Vec2, Widget};
 use bevy_egui::{egui, EguiContexts, EguiPlugin, EguiSettings};
 use enum_iterator::{all, cardinality};
 
+use crate::events::{Upgrade, UpgradePurchased};
 use crate::level::{GameStats, Gameplay, Level, LevelLocal};
-use crate::unit::{Spawner, UnitPrefab};
+use crate::pool::{Benchmark, Pool};
+use crate::settings::Settings;
+use crate::stats::Statistics;
+use crate::tower::Tower;
+use crate::unit::{Batch, Formation, Spawner, UnitPrefab};
 
 fn main_menu(mut contexts: EguiContexts, mut next_level: ResMut<NextState<Level>>) {
     contexts.ctx_mut().set_visuals(egui::Visuals::light());
@@ -64,42 +70,185 @@ fn main_menu(mut contexts: EguiContexts, mut next_level: ResMut<NextState<Level>
         });
 }
 
+fn results(
+    ui: &mut egui::Ui,
+    statistics: &Statistics,
+    stats: &GameStats,
+    towers: &Query<Entity, With<Tower>>,
+    time: &Time,
+) {
+    ui.vertical_centered(|ui| {
+        let stars = statistics.stars(stats, time.elapsed());
+        ui.label(
+            RichText::new("★".repeat(stars as usize) + &"☆".repeat(3 - stars as usize))
+                .size(40.0)
+                .color(Color32::GOLD),
+        );
+    });
+    egui::Grid::new("results")
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui| {
+            let taken = statistics.time_taken(stats, time.elapsed()).as_secs();
+            ui.label("Time taken");
+            ui.label(format!("{}:{:02}", taken / 60, taken % 60));
+            ui.end_row();
+            ui.label("Souls collected");
+            ui.label(stats.souls_total.to_string());
+            ui.end_row();
+            ui.label("Souls spent on upgrades");
+            ui.label(statistics.upgrade_souls.to_string());
+            ui.end_row();
+            for upgrade in all::<Upgrade>() {
+                if let Some(count) = statistics.upgrades.get(&upgrade) {
+                    ui.label(upgrade.name());
+                    ui.label(count.to_string());
+                    ui.end_row();
+                }
+            }
+        });
+    ui.separator();
+    egui::Grid::new("units")
+        .num_columns(5)
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("Demon");
+            ui.label("Summoned");
+            ui.label("Lost");
+            ui.label("Broke through");
+            ui.label("Lifetime");
+            ui.end_row();
+            for p in all::<UnitPrefab>() {
+                let count = |map: &HashMap<UnitPrefab, u32>| {
+                    map.get(&p).copied().unwrap_or_default().to_string()
+                };
+                if statistics.summoned.contains_key(&p) {
+                    ui.label(p.name());
+                    ui.label(count(&statistics.summoned));
+                    ui.label(count(&statistics.lost));
+                    ui.label(count(&statistics.breached));
+                    ui.label(
+                        statistics
+                            .average_lifetime(p)
+                            .map_or("-".to_string(), |t| format!("{:.1}s", t.as_secs_f32())),
+                    );
+                    ui.end_row();
+                }
+            }
+        });
+    ui.separator();
+    egui::Grid::new("towers")
+        .num_columns(3)
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("Defender");
+            ui.label("Shots fired");
+            ui.label("Damage taken");
+            ui.end_row();
+            for (i, record) in statistics.towers.iter().enumerate() {
+                if towers.contains(record.entity) {
+                    ui.label(format!("Tower {}", i + 1));
+                } else {
+                    ui.label(format!("Tower {} (destroyed)", i + 1));
+                }
+                ui.label(record.shots.to_string());
+                ui.label(format!("{:.0}", record.damage_taken));
+                ui.end_row();
+            }
+        });
+    ui.separator();
+}
+
+#[allow(clippy::too_many_arguments)]
 fn game_ui(
     mut commands: Commands,
     mut contexts: EguiContexts,
     mut spawners: Query<&mut Spawner>,
+    towers: Query<Entity, With<Tower>>,
     time: Res<Time>,
     mut stats: ResMut<GameStats>,
+    statistics: Res<Statistics>,
     mut next: ResMut<NextState<Level>>,
+    mut upgrades: EventWriter<UpgradePurchased>,
+    keys: Res<ButtonInput<KeyCode>>,
 ) {
     contexts.ctx_mut().set_visuals(egui::Visuals::dark());
+    let mut unleash =
+        keys.just_pressed(KeyCode::Space) && !contexts.ctx_mut().wants_keyboard_input();
     egui::SidePanel::left("left")
         .resizable(false)
         .show(contexts.ctx_mut(), |ui| {
             ui.heading("Summoning circles");
+            let held = spawners.iter().any(|s| s.hold);
+            if ui
+                .add_enabled(held, egui::Button::new("Unleash all [Space]"))
+                .clicked()
+            {
+                unleash = true;
+            }
             ui.separator();
             for (i, mut s) in spawners.iter_mut().enumerate() {
-                let mut prefab = s.prefab;
-                egui::ComboBox::from_id_source(i)
-                    .selected_text(s.prefab.name())
+                ui.set_width(ui.available_width());
+                let mut queue = s.queue.clone();
+                let mut remove = None;
+                for (j, batch) in queue.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_id_source((i, j))
+                            .selected_text(batch.prefab.name())
+                            .width(100.0)
+                            .show_ui(ui, |ui| {
+                                for p in all::<UnitPrefab>().take(stats.upgrade_level as usize + 1)
+                                {
+                                    ui.selectable_value(&mut batch.prefab, p, p.name());
+                                }
+                            });
+                        egui::Slider::new(&mut batch.number, 1..=9).integer().ui(ui);
+                        if s.queue.len() > 1 && ui.small_button("✖").clicked() {
+                            remove = Some(j);
+                        }
+                    });
+                }
+                if let Some(j) = remove {
+                    queue.remove(j);
+                }
+                if queue.len() < Spawner::MAX_QUEUE && ui.small_button("Add batch").clicked() {
+                    queue.push(queue.last().copied().unwrap_or_else(Batch::default));
+                }
+                if s.queue != queue {
+                    s.queue = queue;
+                }
+                let mut formation = s.formation;
+                egui::ComboBox::from_id_source(("formation", i))
+                    .selected_text(s.formation.name())
                     .width(ui.available_width())
                     .show_ui(ui, |ui| {
-                        for p in all::<UnitPrefab>().take(stats.upgrade_level as usize + 1) {
-                            ui.selectable_value(&mut prefab, p, p.name());
+                        for f in all::<Formation>() {
+                            ui.selectable_value(&mut formation, f, f.name());
                         }
                     });
-                if s.prefab != prefab {
-                    s.prefab = prefab;
+                if s.formation != formation {
+                    s.formation = formation;
                 }
-                let mut number = s.number;
-                ui.set_width(ui.available_width());
-                egui::Slider::new(&mut number, 1..=9).integer().ui(ui);
-                if number != s.number {
-                    s.number = number;
+                let mut bar =
+                    egui::ProgressBar::new(s.progress(time.elapsed(), stats.upgrade_speed))
+                        .desired_width(ui.available_width());
+                if let Some(batch) = s.batch() {
+                    bar = bar.text(format!("Next: {} × {}", batch.number, batch.prefab.name()));
+                }
+                bar.ui(ui);
+                let mut hold = s.hold;
+                ui.horizontal(|ui| {
+                    ui.toggle_value(&mut hold, "Hold");
+                    if s.stocked() > 0 {
+                        ui.label(format!("{} / {} ready", s.stocked(), Spawner::MAX_STOCK));
+                    }
+                });
+                if unleash {
+                    hold = false;
+                }
+                if s.hold != hold {
+                    s.hold = hold;
                 }
-                egui::ProgressBar::new(s.progress(time.elapsed(), stats.upgrade_speed))
-                    .desired_width(ui.available_width())
-                    .ui(ui);
                 ui.separator();
             }
         });
@@ -133,7 +282,7 @@ fn game_ui(
                 );
             })
         });
-    if stats.defender_morale == 0 {
+    if stats.victory() {
         egui::CentralPanel::default()
             .frame(Frame {
                 fill: Color32::from_white_alpha(128),
@@ -144,6 +293,7 @@ fn game_ui(
             .resizable(false)
             .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
             .show(contexts.ctx_mut(), |ui| {
+                results(ui, &statistics, &stats, &towers, &time);
                 ui.horizontal(|ui| {
                     let width = ui.available_width() / 2.0 - 5.0;
                     if egui::Button::new("Replay")
@@ -162,7 +312,7 @@ fn game_ui(
                     }
                 })
             });
-    } else if stats.time_limit() < time.elapsed() {
+    } else if stats.defeat(time.elapsed()) {
         egui::CentralPanel::default()
             .frame(Frame {
                 fill: Color32::from_black_alpha(128),
@@ -173,6 +323,7 @@ fn game_ui(
             .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
             .resizable(false)
             .show(contexts.ctx_mut(), |ui| {
+                results(ui, &statistics, &stats, &towers, &time);
                 ui.horizontal(|ui| {
                     let width = ui.available_width() / 2.0 - 5.0;
                     if egui::Button::new("Retry")
@@ -199,12 +350,20 @@ fn game_ui(
                     if stats.upgrade_circle < GameStats::MAX_CIRCLES
                         && ui.button("New summoning circle").clicked()
                     {
+                        upgrades.send(UpgradePurchased {
+                            upgrade: Upgrade::Circle,
+                            cost: stats.souls_next,
+                        });
                         stats.souls_current -= stats.souls_next;
                         stats.souls_next += stats.souls_next / 2;
                         stats.upgrade_circle += 1;
                         commands.spawn((LevelLocal, Spawner::default()));
                     }
                     if ui.button("Faster summoning").clicked() {
+                        upgrades.send(UpgradePurchased {
+                            upgrade: Upgrade::Speed,
+                            cost: stats.souls_next,
+                        });
                         stats.souls_current -= stats.souls_next;
                         stats.souls_next += stats.souls_next / 2;
                         stats.upgrade_speed += 1;
@@ -212,11 +371,19 @@ fn game_ui(
                     if stats.upgrade_level + 1 < cardinality::<UnitPrefab>() as u8
                         && ui.button("New type of demon").clicked()
                     {
+                        upgrades.send(UpgradePurchased {
+                            upgrade: Upgrade::Demon,
+                            cost: stats.souls_next,
+                        });
                         stats.souls_current -= stats.souls_next;
                         stats.souls_next += stats.souls_next / 2;
                         stats.upgrade_level += 1;
                     }
                     if ui.button("Appease the archdemon").clicked() {
+                        upgrades.send(UpgradePurchased {
+                            upgrade: Upgrade::Appease,
+                            cost: stats.souls_next,
+                        });
                         stats.souls_current -= stats.souls_next;
                         stats.souls_next += stats.souls_next / 2;
                         stats.upgrade_appease += 1;
@@ -226,6 +393,55 @@ fn game_ui(
     }
 }
 
+fn settings_ui(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<Settings>,
+    mut open: Local<bool>,
+    keys: Res<ButtonInput<KeyCode>>,
+    pool: Res<Pool>,
+    mut benchmark: ResMut<Benchmark>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        *open = !*open;
+    }
+    egui::Area::new("settings_button")
+        .anchor(Align2::RIGHT_TOP, Vec2::new(-10.0, 10.0))
+        .show(contexts.ctx_mut(), |ui| {
+            if ui.button("Settings").clicked() {
+                *open = !*open;
+            }
+        });
+    egui::Window::new("Settings")
+        .open(&mut open)
+        .anchor(Align2::RIGHT_TOP, Vec2::new(-10.0, 40.0))
+        .resizable(false)
+        .collapsible(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut settings.health_bars, "Health bars");
+            ui.checkbox(&mut settings.damage_numbers, "Damage numbers");
+            ui.checkbox(&mut settings.screen_shake, "Screen shake");
+            ui.checkbox(&mut settings.minimap, "Minimap");
+            ui.checkbox(&mut settings.pooling, "Object pooling");
+            ui.label(format!(
+                "Pool: {} reused / {} loaded",
+                pool.hits, pool.misses
+            ));
+            if benchmark.running() {
+                egui::ProgressBar::new(benchmark.progress())
+                    .text("Benchmarking")
+                    .ui(ui);
+            } else if ui.button("Benchmark pooling").clicked() {
+                benchmark.start();
+            }
+            if let Some((pooled, unpooled)) = benchmark.result {
+                ui.label(format!(
+                    "{:.1} ms per frame with pooling, {:.1} ms without",
+                    pooled, unpooled
+                ));
+            }
+        });
+}
+
 fn setup_ui(mut contexts: EguiContexts, mut set: ResMut<EguiSettings>) {
     let mut fonts = egui::FontDefinitions::default();
     fonts.font_data.insert(
@@ -256,7 +472,7 @@ impl Plugin for UiPlugin {
         )
         .add_systems(
             Update,
-            game_ui
+            (game_ui, settings_ui)
                 .in_set(Gameplay)
                 .run_if(not(in_state(Level::MainMenu))),
         );
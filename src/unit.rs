@@ -1,15 +1,22 @@
 use std::time::Duration;
 
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
 use enum_iterator::Sequence;
 use space_editor::prelude::*;
 
+use crate::camera::CameraPath;
+use crate::events::{UnitDied, UnitReachedGoal, UnitSpawned};
+use crate::faction::Faction;
 use crate::fx::Spawnable;
 use crate::level::{GameStats, Gameplay, LevelLocal};
-use crate::spline::{Curve, FollowCurve, Width};
+use crate::pool::{Pool, Recycler, Released};
+use crate::spells::Hasted;
+use crate::spline::{follow_curve, Curve, FollowCurve, PathEnd, Width};
 use crate::utils::get_random_from_iter;
 
-#[derive(Clone, Copy, Reflect, Default, PartialEq, Eq, Sequence)]
+#[derive(Clone, Copy, Reflect, Default, PartialEq, Eq, Hash, Sequence)]
 #[reflect(Default)]
 pub enum UnitPrefab {
     #[default]
@@ -17,6 +24,7 @@ pub enum UnitPrefab {
     Ghoul,
     Hellhound,
     DarkKnight,
+    Shade,
 }
 
 impl UnitPrefab {
@@ -27,6 +35,7 @@ impl UnitPrefab {
             UnitPrefab::Ghoul => "scenes/Ghoul.scn.ron",
             UnitPrefab::Hellhound => "scenes/Hellhound.scn.ron",
             UnitPrefab::DarkKnight => "scenes/DarkKnight.scn.ron",
+            UnitPrefab::Shade => "scenes/Shade.scn.ron",
         }
     }
 
@@ -37,6 +46,7 @@ impl UnitPrefab {
             UnitPrefab::Ghoul => 2.0,
             UnitPrefab::Hellhound => 2.0,
             UnitPrefab::DarkKnight => 4.0,
+            UnitPrefab::Shade => 3.0,
         }
     }
 
@@ -47,6 +57,7 @@ impl UnitPrefab {
             UnitPrefab::Ghoul => "Ghoul",
             UnitPrefab::Hellhound => "Hellhound",
             UnitPrefab::DarkKnight => "Dark Knight",
+            UnitPrefab::Shade => "Shade",
         }
     }
 }
@@ -63,13 +74,98 @@ impl Default for Unit {
     }
 }
 
+/// Units that towers can only target when detected or revealed
+#[derive(Component, Reflect, Clone, Copy, Default)]
+#[reflect(Component, Default)]
+pub struct Stealth;
+
+/// The prefab a unit was summoned from
+#[derive(Component, Clone, Copy)]
+pub struct Summoned(pub UnitPrefab);
+
+/// How the units from one summoning move together
+#[derive(Clone, Copy, Reflect, Default, PartialEq, Eq, Sequence)]
+#[reflect(Default)]
+pub enum Formation {
+    /// Every unit for itself
+    #[default]
+    Loose,
+    Wedge,
+    Column,
+    Line,
+}
+
+impl Formation {
+    const SPACING: f32 = 1.2;
+
+    #[inline]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Formation::Loose => "Loose",
+            Formation::Wedge => "Wedge",
+            Formation::Column => "Column",
+            Formation::Line => "Line",
+        }
+    }
+
+    /// Position of the `i`:th of `n` units, relative to the front of the squad (along, lateral),
+    /// on a lane reaching `width` to each side
+    fn slot(&self, i: usize, n: usize, width: f32) -> Vec2 {
+        let i = i as f32;
+        match self {
+            Formation::Loose => Vec2::ZERO,
+            Formation::Wedge => {
+                let row = ((i + 1.0) * 0.5).floor();
+                let side = if i as usize % 2 == 1 { -1.0 } else { 1.0 };
+                Vec2::new(-row, side * row * 0.5) * Self::SPACING
+            }
+            Formation::Column => Vec2::new(-i, 0.0) * Self::SPACING,
+            Formation::Line => {
+                // Squeeze wide lines into the lane instead of stacking the outer units at its edges
+                let spacing = Self::SPACING.min(2.0 * width / n as f32);
+                Vec2::new(0.0, (i - (n as f32 - 1.0) * 0.5) * spacing)
+            }
+        }
+    }
+}
+
+/// A unit moving in formation with the others summoned at the same time
+#[derive(Component, Clone, Copy)]
+pub struct Squad {
+    id: u32,
+    curve: Entity,
+    slot: Vec2,
+}
+
+/// Hands out ids for new squads, since pooled entities are reused
+#[derive(Resource, Default)]
+pub struct SquadIds(u32);
+
+impl SquadIds {
+    fn next(&mut self) -> u32 {
+        self.0 = self.0.wrapping_add(1);
+        self.0
+    }
+}
+
+#[allow(clippy::type_complexity)]
 pub fn instantiate_unit(
     mut commands: Commands,
-    units: Query<(Entity, &Unit), Without<FollowCurve>>,
-    curves: Query<(Entity, Option<&Width>), With<Curve>>,
+    units: Query<(Entity, &Unit, Option<&Parent>), Without<FollowCurve>>,
+    markers: Query<(Option<&Summoned>, Option<&Squad>)>,
+    curves: Query<(Entity, Option<&Width>), (With<Curve>, Without<CameraPath>)>,
+    mut spawned: EventWriter<UnitSpawned>,
 ) {
-    for (entity, unit) in units.iter() {
-        match get_random_from_iter(|| curves.iter()) {
+    for (entity, unit, parent) in units.iter() {
+        // The markers are added to the prefab, which is the parent of the unit
+        let own = markers.get(entity).ok();
+        let inherited = parent.and_then(|p| markers.get(p.get()).ok());
+        let summoned = own.and_then(|m| m.0).or(inherited.and_then(|m| m.0));
+        let squad = own.and_then(|m| m.1).or(inherited.and_then(|m| m.1));
+        let curve = squad
+            .and_then(|s| curves.get(s.curve).ok())
+            .or_else(|| get_random_from_iter(|| curves.iter()));
+        match curve {
             None => continue,
             Some((curve, width)) => {
                 let radius = width.map(|w| w.0).unwrap_or(1.0);
@@ -79,96 +175,398 @@ pub fn instantiate_unit(
                     .insert(FollowCurve::new(
                         curve,
                         unit.speed,
-                        Vec3::new(
-                            radius * fastrand::f32() * 2.0 - radius,
-                            0.0,
-                            radius * fastrand::f32() * 2.0 - radius,
-                        ),
+                        squad.map_or(radius * fastrand::f32() * 2.0 - radius, |s| s.slot.y),
+                        radius,
                     ));
+                if let Some(summoned) = summoned {
+                    commands.entity(entity).insert(*summoned);
+                    spawned.send(UnitSpawned {
+                        entity,
+                        prefab: summoned.0,
+                    });
+                }
+                if let Some(squad) = squad {
+                    commands.entity(entity).insert(*squad);
+                }
             }
         }
     }
 }
 
+/// Units waiting in the pool, with the `Unit` and `Health` put aside so they are left alone
+#[derive(Component, Clone, Copy)]
+struct Dormant(Unit, Health);
+
+/// Return units to the pool, resetting them for the next summon
+#[derive(SystemParam)]
+pub struct Retire<'w, 's> {
+    recycler: Recycler<'w, 's>,
+    units: Query<'w, 's, (&'static Unit, &'static Health, Option<&'static MaxHealth>)>,
+}
+
+impl Retire<'_, '_> {
+    pub fn unit(&mut self, commands: &mut Commands, entity: Entity) {
+        if !self.recycler.recycle(commands, entity) {
+            return;
+        }
+        let Ok((unit, health, max_health)) = self.units.get(entity) else {
+            return;
+        };
+        let health = Health(max_health.map_or(health.0, |m| m.0));
+        commands
+            .entity(entity)
+            .remove::<(Unit, Health, FollowCurve, Squad, Summoned, Hasted)>()
+            .insert(Dormant(*unit, health));
+    }
+}
+
+/// Restore units that have been taken from the pool
+fn wake_units(
+    mut commands: Commands,
+    units: Query<(Entity, &Dormant, &Parent)>,
+    released: Query<(), With<Released>>,
+) {
+    for (entity, dormant, parent) in units.iter() {
+        if !released.contains(parent.get()) {
+            commands
+                .entity(entity)
+                .remove::<Dormant>()
+                .insert((dormant.0, dormant.1));
+        }
+    }
+}
+
+/// Spread crowds across the lane, letting faster units steer around slower ones
+fn separate(
+    mut units: Query<(Entity, &mut FollowCurve, Option<&Squad>), With<Unit>>,
+    time: Res<Time>,
+) {
+    /// Personal space of a unit
+    const RADIUS: f32 = 0.8;
+    /// How far ahead faster units look for slower ones to overtake
+    const LOOKAHEAD: f32 = 2.5;
+    const STRENGTH: f32 = 3.0;
+
+    let mut crowd: Vec<_> = units
+        .iter()
+        .map(|(e, f, s)| {
+            let squad = s.map(|s| s.id);
+            (e, f.curve(), f.along(), f.lateral(), f.speed(), squad)
+        })
+        .collect();
+    crowd.sort_by(|a, b| a.1.cmp(&b.1).then(a.2.total_cmp(&b.2)));
+    let step = STRENGTH * time.delta_seconds();
+    for (i, &(entity, curve, along, lateral, speed, squad)) in crowd.iter().enumerate() {
+        let Ok((_, mut follow, _)) = units.get_mut(entity) else {
+            continue;
+        };
+        let behind = crowd[..i]
+            .iter()
+            .rev()
+            .take_while(|o| o.1 == curve && along - o.2 < LOOKAHEAD);
+        let ahead = crowd[i + 1..]
+            .iter()
+            .take_while(|o| o.1 == curve && o.2 - along < LOOKAHEAD);
+        let mut push = Vec2::ZERO;
+        for &(other, _, a, l, s, sq) in behind.chain(ahead) {
+            if squad.is_some() && squad == sq {
+                // Squads keep their own spacing
+                continue;
+            }
+            let diff = Vec2::new(along - a, lateral - l);
+            let dist = diff.length();
+            if dist < RADIUS {
+                let dir = if dist > f32::EPSILON {
+                    diff / dist
+                } else if entity < other {
+                    Vec2::NEG_Y
+                } else {
+                    Vec2::Y
+                };
+                push += dir * (RADIUS - dist) / RADIUS;
+            }
+            if a > along && s < speed && (lateral - l).abs() < RADIUS {
+                // Overtake on the side with more room
+                let mut side = if lateral >= l { 1.0 } else { -1.0 };
+                if (lateral + side * RADIUS).abs() > follow.width() {
+                    side = -side;
+                }
+                push.y += side * (1.0 - (a - along) / LOOKAHEAD);
+            }
+        }
+        if push != Vec2::ZERO {
+            follow.nudge(push.x * step, push.y * step);
+        }
+    }
+}
+
 #[derive(Component, Clone, Copy, Reflect)]
 #[reflect(Component, Default)]
 pub struct Health(pub f32);
 
+impl Health {
+    /// Reduce the health and return how much was actually lost
+    pub fn hurt(&mut self, damage: f32) -> f32 {
+        let lost = damage.min(self.0.max(0.0));
+        self.0 -= damage;
+        lost
+    }
+}
+
+/// The health a unit was spawned with
+#[derive(Component, Clone, Copy)]
+pub struct MaxHealth(pub f32);
+
+#[allow(clippy::type_complexity)]
+fn init_max_health(
+    mut commands: Commands,
+    q: Query<(Entity, &Health), (With<Unit>, Without<MaxHealth>)>,
+) {
+    for (entity, health) in q.iter() {
+        commands
+            .get_entity(entity)
+            .unwrap()
+            .insert(MaxHealth(health.0));
+    }
+}
+
 impl Default for Health {
     fn default() -> Self {
         Self(100.0)
     }
 }
 
-#[derive(Component, Reflect, Clone, Copy)]
-#[reflect(Component, Default)]
-pub struct Spawner {
+/// A number of units summoned together
+#[derive(Reflect, Clone, Copy, PartialEq)]
+#[reflect(Default)]
+pub struct Batch {
     pub prefab: UnitPrefab,
     pub number: u8,
+}
+
+impl Default for Batch {
+    fn default() -> Self {
+        Self {
+            prefab: Default::default(),
+            number: 1,
+        }
+    }
+}
+
+impl Batch {
+    #[inline]
+    pub fn cost(&self) -> f32 {
+        self.prefab.cost() * self.number as f32
+    }
+}
+
+#[derive(Component, Reflect, Clone)]
+#[reflect(Component, Default)]
+pub struct Spawner {
+    /// Summoned in order, starting over after the last batch
+    pub queue: Vec<Batch>,
+    pub formation: Formation,
+    /// Keep the summoned batches in the circle until released
+    #[reflect(ignore)]
+    pub hold: bool,
+    #[reflect(ignore)]
+    stock: Vec<Batch>,
+    #[reflect(ignore)]
+    index: usize,
     #[reflect(ignore)]
     prev: Duration,
 }
 
 impl Spawner {
+    pub const MAX_QUEUE: usize = 5;
+    pub const MAX_STOCK: usize = 3;
+
+    /// Number of batches held in the circle
+    #[inline]
+    pub fn stocked(&self) -> usize {
+        self.stock.len()
+    }
+
+    /// The batch that will be summoned next
+    #[inline]
+    pub fn batch(&self) -> Option<Batch> {
+        if self.queue.is_empty() {
+            None
+        } else {
+            Some(self.queue[self.index % self.queue.len()])
+        }
+    }
+
     #[inline]
     pub fn progress(&self, time: Duration, speed: u8) -> f32 {
-        ((time - self.prev).as_secs_f32() / self.total_cooldown(speed).as_secs_f32()).min(1.0)
+        let cooldown = self.batch_cooldown(speed);
+        if cooldown.is_zero() {
+            return 0.0;
+        }
+        ((time - self.prev).as_secs_f32() / cooldown.as_secs_f32()).min(1.0)
     }
 
+    /// Time to summon the next batch in the queue
     #[inline]
-    pub fn total_cooldown(&self, speed: u8) -> Duration {
-        Duration::from_secs_f32(
-            self.prefab.cost() * (self.number as f32) * 5.0 / (5.0 + speed as f32),
-        )
+    pub fn batch_cooldown(&self, speed: u8) -> Duration {
+        Duration::from_secs_f32(self.batch().map_or(0.0, |b| b.cost()) * 5.0 / (5.0 + speed as f32))
     }
 
     #[inline]
     pub fn next(&self, speed: u8) -> Duration {
-        self.prev + self.total_cooldown(speed)
+        self.prev + self.batch_cooldown(speed)
     }
 }
 
 impl Default for Spawner {
     fn default() -> Self {
         Self {
-            prefab: Default::default(),
+            queue: vec![Batch::default()],
+            formation: Formation::default(),
+            hold: false,
+            stock: Vec::new(),
+            index: 0,
             prev: Duration::from_secs(0),
-            number: 1,
         }
     }
 }
 
+/// Move squads together at the pace of their slowest member
+fn keep_formation(mut units: Query<(&Unit, &Squad, &mut FollowCurve)>, time: Res<Time>) {
+    /// How quickly units move towards their place in the formation
+    const GAIN: f32 = 1.0;
+
+    // The pace and the position of the front of each squad
+    let mut squads: HashMap<u32, (f32, f32)> = HashMap::new();
+    for (unit, squad, follow) in units.iter() {
+        let (pace, front) = squads.entry(squad.id).or_insert((f32::MAX, f32::MAX));
+        *pace = pace.min(unit.speed);
+        *front = front.min(follow.distance() - squad.slot.x);
+    }
+    for (unit, squad, mut follow) in units.iter_mut() {
+        let (pace, front) = squads[&squad.id];
+        let behind = front + squad.slot.x - follow.distance();
+        follow.set_speed((pace + behind * GAIN).clamp(0.0, unit.speed));
+        let width = follow.width();
+        let lateral = squad.slot.y.clamp(-width, width) - follow.lateral();
+        follow.nudge(0.0, lateral * GAIN * time.delta_seconds());
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn summon(
+    commands: &mut Commands,
+    pool: &mut Pool,
+    ids: &mut SquadIds,
+    batch: Batch,
+    formation: Formation,
+    curves: &Query<(Entity, Option<&Width>), (With<Curve>, Without<CameraPath>)>,
+) {
+    let curve = match formation {
+        Formation::Loose => None,
+        _ => get_random_from_iter(|| curves.iter()),
+    };
+    let id = ids.next();
+    for i in 0..batch.number {
+        // `UnitSpawned` is sent once the unit inside the prefab has been placed on its curve
+        let entity = pool
+            .spawn(commands, batch.prefab.path())
+            .insert((LevelLocal, Summoned(batch.prefab)))
+            .id();
+        if let Some((curve, width)) = curve {
+            commands.entity(entity).insert(Squad {
+                id,
+                curve,
+                slot: formation.slot(
+                    i as usize,
+                    batch.number as usize,
+                    width.map_or(1.0, |w| w.0),
+                ),
+            });
+        } else {
+            commands.entity(entity).remove::<Squad>();
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
 pub fn tick_spawners(
     mut commands: Commands,
     mut spawners: Query<&mut Spawner>,
+    curves: Query<(Entity, Option<&Width>), (With<Curve>, Without<CameraPath>)>,
     time: Res<Time>,
     stats: Res<GameStats>,
+    mut pool: ResMut<Pool>,
+    mut ids: ResMut<SquadIds>,
 ) {
     let time = time.elapsed();
     for mut spawner in spawners.iter_mut() {
-        if spawner.next(stats.upgrade_speed) < time {
+        let Some(batch) = spawner.batch() else {
+            continue;
+        };
+        if spawner.next(stats.upgrade_speed) < time
+            && (!spawner.hold || spawner.stock.len() < Spawner::MAX_STOCK)
+        {
             spawner.prev = time;
-            for _ in 0..spawner.number {
-                commands
-                    .spawn(PrefabBundle::new(spawner.prefab.path()))
-                    .insert(LevelLocal);
+            spawner.index = (spawner.index + 1) % spawner.queue.len();
+            if spawner.hold {
+                spawner.stock.push(batch);
+            } else {
+                summon(
+                    &mut commands,
+                    &mut pool,
+                    &mut ids,
+                    batch,
+                    spawner.formation,
+                    &curves,
+                );
+            }
+        }
+        if !spawner.hold && !spawner.stock.is_empty() {
+            let formation = spawner.formation;
+            for batch in spawner.stock.drain(..) {
+                summon(
+                    &mut commands,
+                    &mut pool,
+                    &mut ids,
+                    batch,
+                    formation,
+                    &curves,
+                );
             }
         }
     }
 }
 
+#[allow(clippy::type_complexity)]
 pub fn die(
     mut commands: Commands,
-    q: Query<(Entity, &Health, &GlobalTransform, Option<&Spawnable>), Changed<Health>>,
+    q: Query<
+        (
+            Entity,
+            &Health,
+            &GlobalTransform,
+            Option<&Spawnable>,
+            Option<&Summoned>,
+        ),
+        Changed<Health>,
+    >,
     mut stats: ResMut<GameStats>,
+    mut died: EventWriter<UnitDied>,
+    mut retire: Retire,
 ) {
-    for (entity, health, gt, spawnable) in q.iter() {
+    for (entity, health, gt, spawnable, summoned) in q.iter() {
         if health.0 <= 0.0 {
             stats.souls_current += 1;
             stats.souls_total += 1;
-            commands.get_entity(entity).unwrap().despawn_recursive();
+            died.send(UnitDied {
+                entity,
+                prefab: summoned.map(|s| s.0),
+                position: gt.translation(),
+            });
+            retire.unit(&mut commands, entity);
             if let Some(spawnable) = spawnable {
-                spawnable.spawn(gt.translation(), &mut commands);
+                spawnable.spawn(gt.translation(), &mut commands, retire.recycler.pool());
             }
         }
     }
@@ -186,22 +584,122 @@ impl Default for Goal {
     }
 }
 
+impl Goal {
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+}
+
+fn reach_goal(
+    commands: &mut Commands,
+    retire: &mut Retire,
+    entity: Entity,
+    summoned: Option<&Summoned>,
+    position: Vec3,
+    stats: &mut GameStats,
+    reached: &mut EventWriter<UnitReachedGoal>,
+) {
+    retire.unit(commands, entity);
+    reached.send(UnitReachedGoal {
+        entity,
+        prefab: summoned.map(|s| s.0),
+        position,
+    });
+    if stats.defender_morale > 0 {
+        stats.defender_morale -= 1;
+    }
+}
+
 fn score(
     mut commands: Commands,
-    goals: Query<(&Goal, &GlobalTransform)>,
-    units: Query<(Entity, &GlobalTransform), With<Unit>>,
+    goals: Query<(&Goal, &GlobalTransform, &Faction)>,
+    units: Query<(Entity, &GlobalTransform, Option<&Summoned>, &Faction), With<Unit>>,
     mut stats: ResMut<GameStats>,
+    mut reached: EventWriter<UnitReachedGoal>,
+    mut retire: Retire,
 ) {
-    for (goal, gt) in goals.iter() {
+    for (goal, gt, goal_faction) in goals.iter() {
         let pos = gt.translation();
         let rad = goal.radius * goal.radius;
-        for (e, gt) in units.iter() {
-            if pos.distance_squared(gt.translation()) < rad {
-                commands.get_entity(e).unwrap().despawn_recursive();
-                if stats.defender_morale > 0 {
-                    stats.defender_morale -= 1;
+        for (e, gt, summoned, faction) in units.iter() {
+            if faction.hostile(*goal_faction) && pos.distance_squared(gt.translation()) < rad {
+                reach_goal(
+                    &mut commands,
+                    &mut retire,
+                    e,
+                    summoned,
+                    gt.translation(),
+                    &mut stats,
+                    &mut reached,
+                );
+            }
+        }
+    }
+}
+
+/// Handle units that have reached the end of their curve without reaching a goal
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn end_of_path(
+    mut commands: Commands,
+    mut units: Query<
+        (
+            Entity,
+            &mut FollowCurve,
+            &GlobalTransform,
+            Option<&Summoned>,
+        ),
+        With<Unit>,
+    >,
+    curves: Query<(Entity, &Curve, &GlobalTransform, Option<&PathEnd>), Without<CameraPath>>,
+    goals: Query<(&Goal, &GlobalTransform)>,
+    mut stats: ResMut<GameStats>,
+    mut reached: EventWriter<UnitReachedGoal>,
+    mut warned: Local<HashSet<Entity>>,
+    mut retire: Retire,
+) {
+    for (entity, mut follow, gt, summoned) in units.iter_mut() {
+        if !follow.finished() {
+            continue;
+        }
+        let Ok((curve_entity, curve, curve_gt, end)) = curves.get(follow.curve()) else {
+            continue;
+        };
+        let end = end.copied().unwrap_or_default();
+        let end_pos = curve_gt.transform_point(curve.end());
+        // Checked here, rather than when the curve is created, so that the goals have been loaded
+        if warned.insert(curve_entity)
+            && !goals.iter().any(|(goal, gt)| {
+                gt.translation().distance(end_pos) < goal.radius + Curve::LINK_DISTANCE
+            })
+        {
+            warn!(
+                "Curve {:?} has no goal at its end, units will {:?}",
+                curve_entity, end
+            );
+        }
+        match end {
+            PathEnd::Despawn => retire.unit(&mut commands, entity),
+            PathEnd::Score => reach_goal(
+                &mut commands,
+                &mut retire,
+                entity,
+                summoned,
+                gt.translation(),
+                &mut stats,
+                &mut reached,
+            ),
+            PathEnd::Continue => {
+                let next = curves
+                    .iter()
+                    .filter(|(e, ..)| *e != curve_entity)
+                    .map(|(e, c, gt, _)| (e, gt.transform_point(c.start()).distance(end_pos)))
+                    .filter(|(_, d)| *d < Curve::LINK_DISTANCE)
+                    .min_by(|a, b| a.1.total_cmp(&b.1));
+                if let Some((next, _)) = next {
+                    follow.switch(next);
                 }
             }
+            PathEnd::Idle => {}
         }
     }
 }
@@ -221,9 +719,31 @@ impl Plugin for UnitPlugin {
             .editor_registry::<Unit>()
             .editor_registry::<Spawner>()
             .editor_registry::<Goal>()
+            .editor_registry::<Stealth>()
             .register_type::<UnitPrefab>()
-            .add_systems(PreUpdate, instantiate_unit.in_set(Gameplay))
-            .add_systems(Update, (die, tick_spawners, score).in_set(Gameplay));
+            .register_type::<Formation>()
+            .register_type::<Batch>()
+            .register_type::<Vec<Batch>>()
+            .init_resource::<SquadIds>()
+            .add_systems(
+                PreUpdate,
+                (
+                    wake_units.before(instantiate_unit),
+                    instantiate_unit,
+                    init_max_health,
+                )
+                    .in_set(Gameplay),
+            )
+            .add_systems(
+                Update,
+                (
+                    // Retired units lose `Unit` and `Health`, so only one of these handles each unit
+                    (die, score, end_of_path).chain(),
+                    tick_spawners,
+                    (separate, keep_formation).before(follow_curve),
+                )
+                    .in_set(Gameplay),
+            );
         #[cfg(feature = "editor")]
         {
             app.add_systems(Update, draw_gizmos);